@@ -0,0 +1,47 @@
+// Interpreter throughput with and without the decode cache, and with
+// compiled blocks.
+//
+//     cargo bench --bench interpreter
+
+use std::hint::black_box;
+use std::time::Instant;
+use chip8_emulator::cpu::CPU;
+
+const INSTRUCTIONS: u64 = 20_000_000;
+
+// a tight loop of arithmetic, skips, BCD stores outside the code and a draw
+const PROGRAM: [u16; 10] = [
+    0xA300, // 200: I = 300
+    0x7001, // 202: V0 += 1
+    0x8104, // 204: V1 += V0
+    0x8216, // 206: V2 = V1 >> 1
+    0x8323, // 208: V3 ^= V2
+    0x3000, // 20A: skip if V0 == 0
+    0x4101, // 20C: skip if V1 != 1
+    0xF033, // 20E: BCD of V0 at I
+    0xD231, // 210: draw one row at V2, V3
+    0x1202, // 212: jump 202
+];
+
+fn measure(cached: bool, jit: bool) -> f64 {
+    let rom: Vec<u8> = PROGRAM.iter().flat_map(|w| w.to_be_bytes()).collect();
+    let mut cpu = CPU::new();
+    cpu.set_decode_cache(cached);
+    cpu.set_jit(jit);
+    cpu.load_bytes(&rom).unwrap();
+    let start = Instant::now();
+    for _ in 0..INSTRUCTIONS / 1000 {
+        cpu.run(1000).unwrap();
+    }
+    black_box(cpu.registers());
+    INSTRUCTIONS as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let uncached = measure(false, false);
+    let cached = measure(true, false);
+    let jit = measure(true, true);
+    println!("decode every instruction: {:7.1} M instr/s", uncached / 1e6);
+    println!("decode cache:             {:7.1} M instr/s  {:5.2}x", cached / 1e6, cached / uncached);
+    println!("compiled blocks:          {:7.1} M instr/s  {:5.2}x", jit / 1e6, jit / uncached);
+}
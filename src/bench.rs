@@ -0,0 +1,141 @@
+// Benchmark mode: runs a ROM flat out, with no frame pacing, output or input,
+// and reports how fast it went.
+//
+// The first pass only counts, so its instructions and frames per second are
+// what the emulator really achieves. A second pass repeats the same number of
+// instructions one at a time through `CPU::execute`, timing each by opcode
+// class (its top nibble) and each frame's display conversion. Timing every
+// instruction slows that pass down; the cost of reading the clock is measured
+// and subtracted, so the breakdown is a good guide to where time goes rather
+// than an exact account.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::cpu::CPU;
+use crate::timing::DEFAULT_IPF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Instructions(u64),
+    Seconds(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchConfig {
+    pub limit: Limit,
+    pub ipf: u32,
+    pub jit: bool,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig { limit: Limit::Seconds(5.0), ipf: DEFAULT_IPF, jit: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClassStats {
+    pub count: u64,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub config: BenchConfig,
+    pub instructions: u64,
+    pub frames: u64,
+    pub elapsed: Duration,         // first pass
+    pub classes: [ClassStats; 16], // second pass, by leading opcode nibble
+    pub display: Duration,         // second pass, converting the display each frame
+}
+
+impl BenchReport {
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        self.frames as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let backend = if self.config.jit { "compiled blocks" } else { "interpreter" };
+        writeln!(f, "{} instructions, {} frames in {:.2} s ({}, {} per frame)", self.instructions, self.frames, self.elapsed.as_secs_f64(), backend, self.config.ipf)?;
+        writeln!(f, "{:10.2} M instructions/s", self.instructions_per_second() / 1e6)?;
+        writeln!(f, "{:10.2} K frames/s", self.frames_per_second() / 1e3)?;
+        writeln!(f)?;
+        let total: Duration = self.classes.iter().map(|c| c.time).sum::<Duration>() + self.display;
+        let share = |t: Duration| 100.0 * t.as_secs_f64() / total.as_secs_f64().max(f64::MIN_POSITIVE);
+        writeln!(f, "time by opcode class (interpreter, clock overhead subtracted):")?;
+        writeln!(f, "  class        count  ns/instr   time")?;
+        for (class, stats) in self.classes.iter().enumerate().filter(|(_, s)| s.count > 0) {
+            let ns = stats.time.as_nanos() as f64 / stats.count as f64;
+            writeln!(f, "  {:X}xxx  {:12}  {:8.1}  {:5.1}%", class, stats.count, ns, share(stats.time))?;
+        }
+        write!(f, "  display {:11}  {:>8}  {:5.1}%", self.frames, "", share(self.display))
+    }
+}
+
+// what reading the clock twice costs, to take out of every timed instruction
+fn clock_overhead() -> Duration {
+    let start = Instant::now();
+    for _ in 0..10_000 {
+        std::hint::black_box(Instant::now().elapsed());
+    }
+    start.elapsed() / 10_000
+}
+
+// the first pass: returns instructions, frames and time taken
+fn measure(rom: &[u8], config: &BenchConfig) -> Result<(u64, u64, Duration), String> {
+    let mut cpu = CPU::new();
+    cpu.set_jit(config.jit);
+    cpu.set_idle_skip(false); // time the loops, not how fast they can be skipped
+    cpu.load_bytes(rom)?;
+    let (width, height) = cpu.output_size();
+    let mut buffer = vec![0u32; width * height];
+    let (mut instructions, mut frames) = (0u64, 0u64);
+    let start = Instant::now();
+    loop {
+        let ipf = match config.limit {
+            Limit::Instructions(n) if instructions >= n => break,
+            Limit::Instructions(n) => (n - instructions).min(config.ipf as u64) as u32,
+            Limit::Seconds(s) if start.elapsed().as_secs_f64() >= s => break,
+            Limit::Seconds(_) => config.ipf,
+        };
+        cpu.run_frame(ipf)?;
+        cpu.update_display_buffer(&mut buffer);
+        instructions += ipf as u64;
+        frames += 1;
+    }
+    Ok((instructions, frames, start.elapsed()))
+}
+
+pub fn run(rom: &[u8], config: &BenchConfig) -> Result<BenchReport, String> {
+    let (instructions, frames, elapsed) = measure(rom, config)?;
+
+    let overhead = clock_overhead();
+    let mut classes = [ClassStats::default(); 16];
+    let mut display = Duration::ZERO;
+    let mut cpu = CPU::new();
+    cpu.load_bytes(rom)?;
+    let (width, height) = cpu.output_size();
+    let mut buffer = vec![0u32; width * height];
+    let mut left = instructions;
+    while left > 0 {
+        for _ in 0..left.min(config.ipf as u64) {
+            let class = &mut classes[(cpu.fetch()? >> 12) as usize];
+            let start = Instant::now();
+            cpu.execute()?;
+            class.time += start.elapsed().saturating_sub(overhead);
+            class.count += 1;
+            left -= 1;
+        }
+        cpu.tick_timers();
+        let start = Instant::now();
+        cpu.update_display_buffer(&mut buffer);
+        display += start.elapsed().saturating_sub(overhead);
+    }
+    Ok(BenchReport { config: config.clone(), instructions, frames, elapsed, classes, display })
+}
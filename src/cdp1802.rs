@@ -0,0 +1,305 @@
+// RCA CDP1802 ("COSMAC") CPU core, the processor of the COSMAC VIP.
+//
+// Sixteen 16-bit registers R0-RF, any of which can be the program counter
+// (selected by P) or the index register (selected by X), an 8-bit accumulator
+// D with carry DF, and the Q output and EF1-EF4 input lines. Everything
+// outside the chip goes through `Bus`: memory, the OUT/INP ports and the EF
+// lines, so the same core can run VIP machine-code subroutines inside the
+// CHIP-8 interpreter or a whole VIP.
+//
+// Each instruction takes two machine cycles of 8 clocks, except the long
+// branches and skips, which take three. DMA and interrupts are requested by
+// whoever owns the bus, between instructions.
+
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+
+    // OUT 1-7
+    fn output(&mut self, _port: u8, _val: u8) {}
+
+    // INP 1-7
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    // EF1-EF4, numbered from 1; true when the line is asserted
+    fn ef(&mut self, _line: u8) -> bool {
+        false
+    }
+
+    // the Q output changed
+    fn q(&mut self, _on: bool) {}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cdp1802 {
+    pub r: [u16; 16],
+    pub d: u8,
+    pub df: bool,
+    pub p: u8,
+    pub x: u8,
+    pub t: u8, // X and P saved by an interrupt or MARK
+    pub ie: bool,
+    pub q: bool,
+    pub idle: bool,  // stopped by IDL until the next DMA or interrupt
+    pub cycles: u64, // machine cycles so far
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cdp1802 {
+    // the state after a hardware reset: R0 is the program counter, at 0
+    pub fn new() -> Self {
+        Cdp1802 { r: [0; 16], d: 0, df: false, p: 0, x: 0, t: 0, ie: true, q: false, idle: false, cycles: 0 }
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let pc = self.p as usize;
+        let byte = bus.read(self.r[pc]);
+        self.r[pc] = self.r[pc].wrapping_add(1);
+        byte
+    }
+
+    fn rx(&self) -> u16 {
+        self.r[self.x as usize]
+    }
+
+    fn set_q(&mut self, on: bool, bus: &mut impl Bus) {
+        if self.q != on {
+            self.q = on;
+            bus.q(on);
+        }
+    }
+
+    // D = a + b + carry
+    fn add(&mut self, a: u8, b: u8, carry: bool) {
+        let sum = a as u16 + b as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // D = a - b - borrow, with DF set when nothing was borrowed
+    fn sub(&mut self, a: u8, b: u8, borrow: bool) {
+        let diff = a as i16 - b as i16 - borrow as i16;
+        self.d = diff as u8;
+        self.df = diff >= 0;
+    }
+
+    // Takes an interrupt if they are enabled: X and P are saved in T, and the
+    // handler runs with R1 as PC and R2 as stack. True if it was taken.
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = self.x << 4 | self.p;
+        self.x = 2;
+        self.p = 1;
+        self.ie = false;
+        self.idle = false;
+        self.cycles += 1;
+        true
+    }
+
+    // One DMA output cycle: the byte at R0 goes to the device and R0 moves on.
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        let byte = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        self.cycles += 1;
+        byte
+    }
+
+    // Runs one instruction and returns the machine cycles it took. Only the
+    // 1804/1805 extended opcode prefix, 0x68, is an error. An idle core
+    // spends the time doing nothing.
+    pub fn step(&mut self, bus: &mut impl Bus) -> Result<u32, String> {
+        if self.idle {
+            self.cycles += 2;
+            return Ok(2);
+        }
+        let at = self.r[self.p as usize];
+        let op = self.fetch(bus);
+        let n = (op & 0xF) as usize;
+        let mut cycles = 2;
+        match op >> 4 {
+            0x0 if n == 0 => self.idle = true, // IDL
+            0x0 => self.d = bus.read(self.r[n]), // LDN
+            0x1 => self.r[n] = self.r[n].wrapping_add(1), // INC
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1), // DEC
+            0x3 => {
+                // short branch within the page: BR, BQ, BZ, BDF, B1-B4, and their negations
+                let taken = match n & 7 {
+                    0 => true,
+                    1 => self.q,
+                    2 => self.d == 0,
+                    3 => self.df,
+                    line => bus.ef(line as u8 - 3),
+                } != (n & 8 != 0);
+                let pc = self.p as usize;
+                if taken {
+                    let target = bus.read(self.r[pc]);
+                    self.r[pc] = self.r[pc] & 0xFF00 | target as u16;
+                } else {
+                    self.r[pc] = self.r[pc].wrapping_add(1);
+                }
+            }
+            0x4 => {
+                // LDA
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[n], self.d), // STR
+            0x6 if n == 0 => self.r[self.x as usize] = self.rx().wrapping_add(1), // IRX
+            0x6 if n == 8 => return Err(format!("Unsupported 1802 instruction 0x68 at 0x{:04X}", at)),
+            0x6 if n < 8 => {
+                // OUT
+                let val = bus.read(self.rx());
+                bus.output(n as u8, val);
+                self.r[self.x as usize] = self.rx().wrapping_add(1);
+            }
+            0x6 => {
+                // INP
+                let val = bus.input(n as u8 - 8);
+                bus.write(self.rx(), val);
+                self.d = val;
+            }
+            0x7 => match n {
+                0x0 | 0x1 => {
+                    // RET, DIS
+                    let t = bus.read(self.rx());
+                    self.r[self.x as usize] = self.rx().wrapping_add(1);
+                    self.x = t >> 4;
+                    self.p = t & 0xF;
+                    self.ie = n == 0;
+                }
+                0x2 => {
+                    // LDXA
+                    self.d = bus.read(self.rx());
+                    self.r[self.x as usize] = self.rx().wrapping_add(1);
+                }
+                0x3 => {
+                    // STXD
+                    bus.write(self.rx(), self.d);
+                    self.r[self.x as usize] = self.rx().wrapping_sub(1);
+                }
+                0x4 => {
+                    let m = bus.read(self.rx());
+                    self.add(m, self.d, self.df) // ADC
+                }
+                0x5 => {
+                    let m = bus.read(self.rx());
+                    self.sub(m, self.d, !self.df) // SDB
+                }
+                0x6 => {
+                    // SHRC
+                    let carry = self.d & 1 != 0;
+                    self.d = self.d >> 1 | (self.df as u8) << 7;
+                    self.df = carry;
+                }
+                0x7 => {
+                    let m = bus.read(self.rx());
+                    self.sub(self.d, m, !self.df) // SMB
+                }
+                0x8 => bus.write(self.rx(), self.t), // SAV
+                0x9 => {
+                    // MARK
+                    self.t = self.x << 4 | self.p;
+                    bus.write(self.r[2], self.t);
+                    self.x = self.p;
+                    self.r[2] = self.r[2].wrapping_sub(1);
+                }
+                0xA => self.set_q(false, bus), // REQ
+                0xB => self.set_q(true, bus),  // SEQ
+                0xC => {
+                    let m = self.fetch(bus);
+                    self.add(m, self.d, self.df) // ADCI
+                }
+                0xD => {
+                    let m = self.fetch(bus);
+                    self.sub(m, self.d, !self.df) // SDBI
+                }
+                0xE => {
+                    // SHLC
+                    let carry = self.d & 0x80 != 0;
+                    self.d = self.d << 1 | self.df as u8;
+                    self.df = carry;
+                }
+                _ => {
+                    let m = self.fetch(bus);
+                    self.sub(self.d, m, !self.df) // SMBI
+                }
+            },
+            0x8 => self.d = self.r[n] as u8,        // GLO
+            0x9 => self.d = (self.r[n] >> 8) as u8, // GHI
+            0xA => self.r[n] = self.r[n] & 0xFF00 | self.d as u16, // PLO
+            0xB => self.r[n] = self.r[n] & 0x00FF | (self.d as u16) << 8, // PHI
+            0xC => {
+                // long branches, long skips and NOP
+                cycles = 3;
+                let cond = match n & 3 {
+                    0 => true,
+                    1 => self.q,
+                    2 => self.d == 0,
+                    _ => self.df,
+                };
+                let pc = self.p as usize;
+                match n {
+                    0x4 => {} // NOP
+                    0x0..=0x3 | 0x9..=0xB => {
+                        // LBR, LBQ, LBZ, LBDF, LBNQ, LBNZ, LBNF
+                        if cond != (n & 8 != 0) {
+                            let hi = bus.read(self.r[pc]);
+                            let lo = bus.read(self.r[pc].wrapping_add(1));
+                            self.r[pc] = (hi as u16) << 8 | lo as u16;
+                        } else {
+                            self.r[pc] = self.r[pc].wrapping_add(2);
+                        }
+                    }
+                    _ => {
+                        // LSNQ, LSNZ, LSNF, LSKP, LSIE, LSQ, LSZ, LSDF
+                        let skip = match n {
+                            0x8 => true,
+                            0xC => self.ie,
+                            _ => cond != (n & 8 == 0),
+                        };
+                        if skip {
+                            self.r[pc] = self.r[pc].wrapping_add(2);
+                        }
+                    }
+                }
+            }
+            0xD => self.p = n as u8, // SEP
+            0xE => self.x = n as u8, // SEX
+            0xF if n & 7 == 6 => {
+                // SHR, SHL
+                if n == 6 {
+                    self.df = self.d & 1 != 0;
+                    self.d >>= 1;
+                } else {
+                    self.df = self.d & 0x80 != 0;
+                    self.d <<= 1;
+                }
+            }
+            _ => {
+                // F0-F7 work on M(R(X)), F8-FF on the immediate byte
+                let m = if n < 8 { bus.read(self.rx()) } else { self.fetch(bus) };
+                match n & 7 {
+                    0 => self.d = m,  // LDX, LDI
+                    1 => self.d |= m, // OR, ORI
+                    2 => self.d &= m, // AND, ANI
+                    3 => self.d ^= m, // XOR, XRI
+                    4 => self.add(m, self.d, false), // ADD, ADI
+                    5 => self.sub(m, self.d, false), // SD, SDI
+                    _ => self.sub(self.d, m, false), // SM, SMI
+                }
+            }
+        }
+        self.cycles += cycles as u64;
+        Ok(cycles)
+    }
+}
@@ -0,0 +1,96 @@
+// CHIP-8X colour, from the VP-590 colour board for the COSMAC VIP.
+//
+// The board colours the monochrome picture rather than adding pixels to it:
+// each lit pixel takes the foreground colour of the area it is in, and every
+// unlit pixel shows the one background colour. Foreground colours are kept
+// per 8 pixel wide, 1 line high cell. BXY0 sets them in zones of 8x4 pixels,
+// the board's low resolution, and BXYN for just the cells a sprite covers.
+// 02A0 steps the background through blue, black, green and red.
+
+pub const COLUMNS: usize = 8; // cells across the 64 pixel display
+pub const LINES: usize = 32;
+const ZONE_LINES: usize = 4;
+
+// the VP-590's colours, by the 3-bit number programs use: bit 0 red, 1 blue, 2 green
+pub const FOREGROUNDS: [u32; 8] = [0x000000, 0xFF0000, 0x0000FF, 0xFF00FF, 0x00FF00, 0xFFFF00, 0x00FFFF, 0xFFFFFF];
+pub const BACKGROUNDS: [u32; 4] = [0x000080, 0x000000, 0x008000, 0x800000];
+const DEFAULT_FOREGROUND: u8 = 1; // red, as the interpreter leaves it
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColourMap {
+    cells: [u8; COLUMNS * LINES], // foreground colour numbers, row by row
+    background: usize,            // index into BACKGROUNDS
+}
+
+impl Default for ColourMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ColourMap {
+    pub fn new() -> Self {
+        ColourMap { cells: [DEFAULT_FOREGROUND; COLUMNS * LINES], background: 0 }
+    }
+
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    // BXY0: `x` holds the first zone column in its low nibble and how many
+    // more to colour in its high nibble, `y` the same for zone rows. Zones
+    // past the edge are left alone.
+    pub fn set_zones(&mut self, x: u8, y: u8, colour: u8) {
+        let columns = (x & 0xF) as usize..=((x & 0xF) + (x >> 4)) as usize;
+        let rows = (y & 0xF) as usize..=((y & 0xF) + (y >> 4)) as usize;
+        for row in rows.filter(|&r| r < LINES / ZONE_LINES) {
+            for line in row * ZONE_LINES..(row + 1) * ZONE_LINES {
+                for column in columns.clone().filter(|&c| c < COLUMNS) {
+                    self.cells[line * COLUMNS + column] = colour & 7;
+                }
+            }
+        }
+    }
+
+    // BXYN: the cells under an 8 x `lines` sprite at pixel `x`, `y`, wrapping
+    // around the edges as the sprite's position does
+    pub fn set_area(&mut self, x: u8, y: u8, lines: u8, colour: u8) {
+        let x = x as usize % (COLUMNS * 8);
+        let mut columns = vec![x / 8];
+        if !x.is_multiple_of(8) {
+            columns.push((x / 8 + 1) % COLUMNS);
+        }
+        for line in (0..lines as usize).map(|l| (y as usize + l) % LINES) {
+            for &column in &columns {
+                self.cells[line * COLUMNS + column] = colour & 7;
+            }
+        }
+    }
+
+    // 0xRRGGBB of a lit pixel
+    pub fn foreground(&self, x: usize, y: usize) -> u32 {
+        FOREGROUNDS[self.cells[y * COLUMNS + x / 8] as usize]
+    }
+
+    // 0xRRGGBB of every unlit pixel
+    pub fn background(&self) -> u32 {
+        BACKGROUNDS[self.background]
+    }
+
+    // the whole map as bytes, for save states
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.cells.to_vec();
+        out.push(self.background as u8);
+        out
+    }
+
+    pub fn load_bytes(&mut self, bytes: &[u8]) {
+        self.cells.copy_from_slice(&bytes[..COLUMNS * LINES]);
+        for cell in &mut self.cells {
+            *cell &= 7;
+        }
+        self.background = bytes[COLUMNS * LINES] as usize % BACKGROUNDS.len();
+    }
+
+    pub const STATE_SIZE: usize = COLUMNS * LINES + 1;
+}
@@ -1,508 +1,1370 @@
-use core::error;
-use std::{fs::File, vec, io};
+use std::fs::File;
 use std::io::{prelude::*, BufReader};
-use std::time::{Duration, Instant};
-use minifb::{Key, Window, WindowOptions};
-use rand::prelude::*;
+use crate::cdp1802::{self, Cdp1802};
+use crate::chip8x::ColourMap;
+use crate::decode::{decode_for, Op};
+use crate::font;
+use crate::framebuffer::Framebuffer;
+use crate::jit::BlockCache;
+use crate::megachip::{self, Blend, MegaChip};
+use crate::profile::MachineProfile;
+use crate::trace::{Category, Tracer};
 
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
-const FONT_START: usize = 0x50;
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const HIRES_HEIGHT: usize = 64; // the two-page HIRES variant
+pub const MAX_HEIGHT: usize = HIRES_HEIGHT;
+const HIRES_SIGNATURE: [u8; 2] = [0x12, 0x60]; // HIRES programs start by jumping to the interpreter patch
+const HIRES_START: usize = 0x2C0; // where they really start
+const HIRES_CLEAR: u16 = 0x230; // 0230 calls the patch's 64x64 clear
+pub const CODE_SIZE: usize = 0x1000; // instructions only run from the 12-bit address space
+const STATE_VERSION: u8 = 4;
+const STATE_STACK_SLOTS: usize = 64; // save states have room for this many return addresses
+const DISPLAY_MEM: usize = 0xF00; // a memory-mapped display starts here, 8 bytes per row as on the COSMAC VIP
+const MEMORY_STACK_END: usize = 0xED0; // a memory stack grows down from here, two bytes per entry
+const MEMORY_STACK_SLOTS: usize = (MEMORY_STACK_END - 0xEA0) / 2;
+const VIP_REGISTERS: usize = 0xEF0; // where the VIP interpreter keeps V0-VF
+const MAX_MACHINE_INSTRUCTIONS: u64 = 1_000_000; // before a 0NNN subroutine counts as hung
+
+// Behaviours that differ between CHIP-8 interpreters. The defaults are what
+// this emulator has always done.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quirks {
+    pub vf_reset: bool,     // 8XY1/8XY2/8XY3 clear VF, as on the COSMAC VIP
+    pub increment_i: bool,  // FX55/FX65 leave I past the last register, as on the COSMAC VIP
+    pub shift_vx: bool,     // 8XY6/8XYE shift VX in place and ignore VY, as on SCHIP
+    pub jump_vx: bool,      // BNNN acts as BXNN and adds VX instead of V0, as on SCHIP
+    pub wrap_sprites: bool, // sprites wrap around the screen edges instead of clipping
+}
+
+// How deep 2NNN can nest and where the return addresses live. A 2NNN past
+// `depth` is a stack overflow and 00EE with nothing to return to an
+// underflow; both stop the CPU with an error, as they would crash a real one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackConfig {
+    pub depth: usize,
+    pub in_memory: bool, // keep return addresses in RAM below 0xED0, as the COSMAC VIP does
+}
+
+impl StackConfig {
+    pub const VIP: StackConfig = StackConfig { depth: 12, in_memory: true };
+    pub const SCHIP: StackConfig = StackConfig { depth: 16, in_memory: false };
+    pub const XO_CHIP: StackConfig = StackConfig { depth: STATE_STACK_SLOTS, in_memory: false };
+
+    // "vip", "schip", "xochip" or a depth
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "vip" => Ok(Self::VIP),
+            "schip" => Ok(Self::SCHIP),
+            "xochip" => Ok(Self::XO_CHIP),
+            _ => {
+                let depth = s.parse::<usize>().map_err(|_| format!("Unknown stack: {}", s))?;
+                Ok(StackConfig { depth, in_memory: false })
+            }
+        }
+    }
+}
+
+// The CHIP-8 dialect a ROM was written for. Each adds or changes a few
+// instructions, and some load programs elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    Chip8X, // VIP with the VP-590 colour board and VP-580 second keypad
+    MegaChip, // MegaChip8, with a true-colour 256x192 mode and 16MB of memory
+    Chip8E, // Gilles Detillieux's CHIP-8E for the VIP, with relative jumps and I/O
+}
+
+impl Platform {
+    // "chip8", "chip8x", "megachip" or "chip8e"
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "chip8" => Ok(Platform::Chip8),
+            "chip8x" => Ok(Platform::Chip8X),
+            "megachip" => Ok(Platform::MegaChip),
+            "chip8e" => Ok(Platform::Chip8E),
+            _ => Err(format!("Unknown platform: {}", s)),
+        }
+    }
+
+    // where programs have to start, if the platform decides rather than the
+    // machine: the CHIP-8X interpreter takes up 0x200-0x2FF too
+    pub fn start_address(self) -> Option<usize> {
+        match self {
+            Platform::Chip8X => Some(0x300),
+            _ => None,
+        }
+    }
+
+    // MegaChip's 24-bit I reaches sprites and samples far past the machine's RAM
+    pub fn memory_size(self) -> Option<usize> {
+        match self {
+            Platform::MegaChip => Some(1 << 24),
+            _ => None,
+        }
+    }
+}
+
+impl Default for StackConfig {
+    fn default() -> Self {
+        Self::SCHIP
+    }
+}
+
+// Loops that spin without changing anything but the instruction count until a
+// timer ticks or a key goes down. Each is recognised from its first instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdleLoop {
+    Key,             // FX0A with no key press waiting
+    Delay { x: u8 }, // FX07, 3X00, then 1NNN back to the FX07, while the delay timer runs
+}
+
+impl IdleLoop {
+    // instructions in one trip round the loop
+    pub fn period(self) -> u64 {
+        match self {
+            IdleLoop::Key => 1,
+            IdleLoop::Delay { .. } => 3,
+        }
+    }
+}
 
 #[derive(Debug)]
+#[allow(non_snake_case)]
 pub struct CPU {
-    mem: [u8; 4 * 1024], // Memory
-    PC: usize, // program counter by bytes
-    display: [[bool; WIDTH as usize]; HEIGHT as usize], // digital display
-    I: u16, // I points to something in memory
+    mem: Vec<u8>, // Memory, sized for the platform
+    pub(crate) PC: usize, // program counter by bytes
+    display: Framebuffer, // digital display, one bit per pixel
+    pub(crate) I: u32, // I points to something in memory
     stack: Vec<usize>, // stack for function / subroutine calls
+    stack_config: StackConfig,
+    memory_display: bool, // whether `display` is mirrored at DISPLAY_MEM
+    hybrid: bool, // whether 0NNN runs CDP1802 machine code
+    platform: Platform,
+    profile: MachineProfile,
+    hires: bool, // a HIRES program, with the 64x64 display
+    colours: ColourMap, // CHIP-8X only
+    megachip: Option<Box<MegaChip>>, // MegaChip only
     delay_timer: u8,
     sound_timer: u8,
-    register: [u8; 16],
+    pub(crate) register: [u8; 16],
+    keypad: [bool; 16], // hex keypad state, fed by whichever frontend is running
+    keypad2: [bool; 16], // the CHIP-8X second keypad
+    pressed_key: Option<u8>, // key that went down since the last keypad update, for FX0A
+    delay_wait: bool, // CHIP-8E FX4F has set the delay timer and waits for it to run out
+    output: u8, // last byte CHIP-8E FX03 sent to the VIP's output port
+    input: u8, // byte on the VIP's input port, for CHIP-8E FXE3/FXE7
+    input_strobe: bool, // `input` is new, so FXE3 can stop waiting
+    quirks: Quirks,
+    pub(crate) cycle: u64, // instructions executed so far
+    tracer: Tracer,
+    decoded: Vec<Option<Op>>, // decode cache, one slot per address an instruction can start at
+    decode_cache: bool,
+    jit: bool, // whether `run` uses compiled blocks
+    blocks: BlockCache,
+    code_written: Option<(usize, usize)>, // bytes written since `blocks` last heard, as start..end
+    idle_skip: bool, // whether `run` fast-forwards idle loops
     pub display_flag: bool,
-    window: Window
 }
 
-const FONT_SET: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80  // F
-];
-
-fn get_chip8_key(key: Key) -> Option<u8> {
-    match key {
-        Key::Key1 => Some(0x1),
-        Key::Key2 => Some(0x2),
-        Key::Key3 => Some(0x3),
-        Key::Key4 => Some(0xC),
-        Key::Q    => Some(0x4),
-        Key::W    => Some(0x5),
-        Key::E    => Some(0x6),
-        Key::R    => Some(0xD),
-        Key::A    => Some(0x7),
-        Key::S    => Some(0x8),
-        Key::D    => Some(0x9),
-        Key::F    => Some(0xE),
-        Key::Z    => Some(0xA),
-        Key::X    => Some(0x0),
-        Key::C    => Some(0xB),
-        Key::V    => Some(0xF),
-        _         => None,
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-fn get_qwerty_key(chip8_key: u8) -> Option<Key> {
-    let key = match chip8_key {
-        0x1 => Some(Key::Key1),
-        0x2 => Some(Key::Key2),
-        0x3 => Some(Key::Key3),
-        0xC => Some(Key::Key4),
-        0x4 => Some(Key::Q),
-        0x5 => Some(Key::W),
-        0x6 => Some(Key::E),
-        0xD => Some(Key::R),
-        0x7 => Some(Key::A),
-        0x8 => Some(Key::S),
-        0x9 => Some(Key::D),
-        0xE => Some(Key::F),
-        0xA => Some(Key::Z),
-        0x0 => Some(Key::X),
-        0xB => Some(Key::C),
-        0xF => Some(Key::V),
-        _ => None, // Invalid keycode
-    };
-    return key;
-}
-
 impl CPU {
     pub fn new() -> Self {
         let mut ret = CPU {
-            mem: [0; 4096],
+            mem: vec![0; CODE_SIZE],
             PC: 0x200, // typical starting address
-            display: [[false; WIDTH]; HEIGHT],
+            display: Framebuffer::new(HEIGHT),
             I: 0,
             stack: Vec::new(),
+            stack_config: StackConfig::default(),
+            memory_display: false,
+            hybrid: false,
+            platform: Platform::default(),
+            profile: MachineProfile::default(),
+            hires: false,
+            colours: ColourMap::new(),
+            megachip: None,
             delay_timer: 0,
             sound_timer: 0,
             register: [0; 16],
+            keypad: [false; 16],
+            keypad2: [false; 16],
+            pressed_key: None,
+            delay_wait: false,
+            output: 0,
+            input: 0,
+            input_strobe: false,
+            quirks: Quirks::default(),
+            cycle: 0,
+            tracer: Tracer::default(),
+            decoded: vec![None; CODE_SIZE],
+            decode_cache: true,
+            jit: false,
+            blocks: BlockCache::default(),
+            code_written: None,
+            idle_skip: true,
             display_flag: false,
-            window: Window::new(
-                "Minifb Test Window",
-                WIDTH,
-                HEIGHT,
-                WindowOptions {
-                    scale: minifb::Scale::X16, // Scale up 10x automatically
-                    ..WindowOptions::default()
-                },
-            ).expect("Failed to create window"),
         };
-        ret.window.limit_update_rate(Some(std::time::Duration::from_millis(16))); // ~60 FPS
-        return ret;
-    }
-    pub fn start(&mut self) -> Result<(), String> {
-        let mut buffer = [0u32; WIDTH * HEIGHT];
-        
-        // Target frequencies:
-        let cpu_frequency: f64 = 600.0; // CPU instructions per second
-        let cpu_cycle_duration = Duration::from_secs_f64(1.0 / cpu_frequency);
-        
-        let timer_interval = Duration::from_millis(16); // ~60Hz timer update
-        let display_interval = Duration::from_millis(16); // ~60FPS display update
-    
-        let mut last_cpu_time = Instant::now();
-        let mut last_timer_time = Instant::now();
-        let mut last_display_time = Instant::now();
-    
-        while self.window.is_open() {
-            let now = Instant::now();
-    
-            // === CPU Cycle: execute as many instructions as the elapsed time allows ===
-            while now.duration_since(last_cpu_time) >= cpu_cycle_duration {
+        let font = ret.profile.font.bytes();
+        ret.mem[font::DEFAULT_START..font::DEFAULT_START + font.len()].copy_from_slice(&font);
+        ret
+    }
+
+    // Sets the machine up like `profile`: memory, font, display and where
+    // programs start. It has to come before loading the ROM.
+    pub fn set_profile(&mut self, profile: MachineProfile) -> Result<(), String> {
+        profile.check()?;
+        let old = self.profile.font_start..self.profile.font_start + self.profile.font.bytes().len();
+        self.mem[old.clone()].fill(0);
+        self.written(old.start, old.len());
+        self.profile = profile;
+        self.mem.resize(self.memory_size(), 0);
+        let font = self.profile.font.bytes();
+        let start = self.profile.font_start;
+        self.mem[start..start + font.len()].copy_from_slice(&font);
+        self.written(start, font.len());
+        self.display = Framebuffer::new(self.profile.height);
+        self.hires = false;
+        self.display_flag = true;
+        self.PC = self.start_address();
+        Ok(())
+    }
+
+    pub fn profile(&self) -> &MachineProfile {
+        &self.profile
+    }
+
+    // where the ROM goes and runs from, for this platform on this machine
+    pub fn start_address(&self) -> usize {
+        self.platform.start_address().unwrap_or(self.profile.start)
+    }
+
+    // bytes of memory the CPU has; never less than the 4K the decode cache covers
+    fn memory_size(&self) -> usize {
+        self.ram_size().max(CODE_SIZE)
+    }
+
+    // bytes a ROM has to fit in
+    fn ram_size(&self) -> usize {
+        self.platform.memory_size().unwrap_or(self.profile.memory)
+    }
+
+    pub fn load_rom(&mut self, filename: &str) -> Result<(), String> {
+        let f = BufReader::new(File::open(filename).map_err(|e| format!("Cannot open {}: {}", filename, e))?);
+        let rom = f.bytes().collect::<Result<Vec<u8>, _>>().map_err(|e| format!("Cannot read {}: {}", filename, e))?;
+        self.load_bytes(&rom)
+    }
+
+    // Loads `rom` at the platform's start address. A CHIP-8 ROM that looks
+    // like a HIRES program (see `is_hires`) gets the 64x64 display.
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<(), String> {
+        let start = self.start_address();
+        if rom.len() > self.ram_size().saturating_sub(start) {
+            return Err(format!("ROM is too large: {} bytes", rom.len()));
+        }
+        self.mem[start..start + rom.len()].copy_from_slice(rom);
+        self.written(start, rom.len());
+        if self.profile.detect_hires && self.platform == Platform::Chip8 && is_hires(rom) {
+            self.set_hires();
+        }
+        Ok(())
+    }
+
+    // On the VIP, HIRES programs come with a patch to the interpreter in
+    // 0x260-0x2BF, so they start after it. Everything else is CHIP-8 as usual,
+    // on a display twice as tall, with 0230 to clear it.
+    fn set_hires(&mut self) {
+        self.hires = true;
+        self.display = Framebuffer::new(HIRES_HEIGHT);
+        self.PC = HIRES_START;
+        self.display_flag = true;
+    }
+
+    pub fn hires(&self) -> bool {
+        self.hires
+    }
+
+    // runs one 60Hz frame: `ipf` instructions followed by a timer tick
+    pub fn run_frame(&mut self, ipf: u32) -> Result<(), String> {
+        self.run(ipf)?;
+        self.tick_timers();
+        Ok(())
+    }
+
+    // Runs `count` instructions, through compiled blocks when they are enabled.
+    // Tracing needs to see every instruction, so it always interprets.
+    // Whenever control goes backwards it checks for an idle loop, and skips
+    // whatever whole trips round it are left.
+    pub fn run(&mut self, count: u32) -> Result<(), String> {
+        if !self.jit || self.tracer.enabled() {
+            let mut left = count as u64;
+            while left > 0 {
+                let pc = self.PC;
                 self.execute()?;
-                last_cpu_time += cpu_cycle_duration;
-            }
-    
-            // === Timer Update: run at 60Hz regardless of CPU speed ===
-            if now.duration_since(last_timer_time) >= timer_interval {
-                if self.delay_timer > 0 {
-                    self.delay_timer -= 1;
+                left -= 1;
+                if self.PC <= pc {
+                    left -= self.skip_idle_within(left);
                 }
-                if self.sound_timer > 0 {
-                    self.sound_timer -= 1;
-                }
-                last_timer_time += timer_interval;
-            }
-    
-            // === Process Input: update your keypad state here if needed ===
-            // (You can poll keys and update a shared keypad array, etc.)
-    
-            // === Display Update: refresh the display at about 60FPS or when flagged ===
-            if now.duration_since(last_display_time) >= display_interval || self.display_flag {
-                self.update_display_buffer(&mut buffer);
-                self.window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
-                last_display_time = now;
-                self.display_flag = false;
-            }
-    
-            // Sleep briefly to avoid busy waiting (adjust as needed)
-            std::thread::sleep(Duration::from_millis(1));
+            }
+            return Ok(());
+        }
+        let mut blocks = std::mem::take(&mut self.blocks);
+        let result = self.run_blocks(&mut blocks, count as usize);
+        self.blocks = blocks;
+        result
+    }
+
+    fn run_blocks(&mut self, blocks: &mut BlockCache, mut left: usize) -> Result<(), String> {
+        while left > 0 {
+            if let Some((start, end)) = self.code_written.take() {
+                blocks.invalidate(start, end - start);
+            }
+            let pc = self.PC;
+            if pc + 1 >= CODE_SIZE {
+                self.execute()?; // no whole instruction to compile; the interpreter fails or goes past 4K
+                left -= 1;
+            } else {
+                let block = blocks.get(&self.mem[..CODE_SIZE], self.quirks, self.platform, self.memory_display, pc);
+                left -= block.run(self, left)?;
+            }
+            if self.PC <= pc {
+                left -= self.skip_idle_within(left as u64) as usize;
+            }
         }
         Ok(())
     }
-    
-    // fn start(&mut self) -> Result<(), String> {
-    //     let mut buffer = [0u32; WIDTH * HEIGHT];
-    //     // execute loop
-    //     let frame_duration = Duration::from_millis(16); // approx 60 fps
-
-    //     let mut last_timer_update = Instant::now();
-    //     let mut last_cycle_update = Instant::now();
-    //     let timer_interval = Duration::from_millis(16); // 60Hz
-    //     let cycle_interval = Duration::from_micros(2000); // 500Hz CPU = 1/500s = 2000μs
-    //     while self.window.is_open(){
-    //         let frame_start = Instant::now();
-    //         // if let keys = self.window.get_keys() {
-    //         //     for key in keys {
-    //         //         println!("Detected key down: {:?}", key);
-    //         //     }
-    //         // }
-    //         // execute 10 instr per frame
-    //         for _ in 0..10 {
-    //             self.execute()?;
-    //         }
-
-    //         if self.display_flag {
-    //             self.update_display_buffer(&mut buffer);
-    //             self.window.update_with_buffer(&buffer, 64, 32).unwrap();
-    //         }
-
-    //         // decrement timers
-    //         self.delay_timer = if self.delay_timer == 0 {60} else {self.delay_timer - 1};
-    //         self.sound_timer = if self.sound_timer == 0 {60} else {self.sound_timer - 1};
-            
-    //         let elapsed = frame_start.elapsed();
-    //         if elapsed < frame_duration {
-    //             std::thread::sleep(frame_duration - elapsed);
-    //         }
-    //     }
-    //     Ok(())
-    // }
-
-    pub fn load_and_execute(&mut self, filename: String) -> Result<(), String> {
-        let f = BufReader::new(File::open(filename).unwrap());
-
-        let mut prog_start = 0x200;
-        for byte in f.bytes() {
-            self.mem[prog_start] = byte.unwrap();
-            prog_start += 1
-        }
-        self.mem[FONT_START..FONT_START + FONT_SET.len()].copy_from_slice(&FONT_SET);
-
-        self.start()
-    }
-
-    pub fn update_display_buffer(&mut self, buffer: &mut [u32; 64 * 32]){
-        // let mut buffer = [0u32; 64 * 32];
-        for y in 0..32 {
-            for x in 0..64 {
-                buffer[y * 64 + x] = if self.display[y][x] {0xFFFFFF} else {0x0};
+
+    // the idle loop starting at PC, if there is one
+    pub fn idle_loop(&self) -> Option<IdleLoop> {
+        let word = |addr: usize| (addr + 1 < self.mem.len()).then(|| (self.mem[addr] as u16) << 8 | self.mem[addr + 1] as u16);
+        match decode_for(self.platform, word(self.PC)?) {
+            Op::WaitKey(_) if self.pressed_key.is_none() => Some(IdleLoop::Key),
+            Op::GetDelay(x)
+                if self.delay_timer > 0
+                    && word(self.PC + 2)? == 0x3000 | (x as u16) << 8
+                    && word(self.PC + 4)? == 0x1000 | self.PC as u16 =>
+            {
+                Some(IdleLoop::Delay { x })
+            }
+            _ => None,
+        }
+    }
+
+    // Leaves the machine as running the idle loop at PC `trips` times would,
+    // without running it, and returns how many instructions that was. Does
+    // nothing when there is no loop, skipping is off or tracing is on.
+    pub fn skip_idle(&mut self, trips: u64) -> u64 {
+        let Some(idle) = self.idle_loop().filter(|_| self.idle_skip && !self.tracer.enabled()) else {
+            return 0;
+        };
+        if let IdleLoop::Delay { x } = idle {
+            if trips > 0 {
+                self.register[x as usize] = self.delay_timer;
+            }
+        }
+        self.cycle += trips * idle.period();
+        trips * idle.period()
+    }
+
+    // skips as many whole trips round an idle loop at PC as fit in `left` instructions
+    fn skip_idle_within(&mut self, left: u64) -> u64 {
+        match self.idle_loop() {
+            Some(idle) => self.skip_idle(left / idle.period()),
+            None => 0,
+        }
+    }
+
+    // Fast-forwarding idle loops is invisible to everything but the host's
+    // CPU usage; turning it off is for comparing the two.
+    pub fn set_idle_skip(&mut self, enabled: bool) {
+        self.idle_skip = enabled;
+    }
+
+    // Compiled blocks (see jit.rs) run the same programs faster than `execute`
+    // in `run` and `run_frame`.
+    pub fn set_jit(&mut self, enabled: bool) {
+        self.jit = enabled;
+        self.blocks = BlockCache::default();
+        self.code_written = None;
+    }
+
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    // replaces the whole keypad state; a key that is down now but wasn't before
+    // becomes available to FX0A until the next update
+    pub fn set_keypad(&mut self, keypad: [bool; 16]) {
+        self.pressed_key = (0..16u8).find(|&k| keypad[k as usize] && !self.keypad[k as usize]);
+        self.keypad = keypad;
+    }
+
+    // the CHIP-8X second keypad, read by EXF2/EXF5
+    pub fn set_keypad2(&mut self, keypad: [bool; 16]) {
+        self.keypad2 = keypad;
+    }
+
+    pub fn keypad2(&self) -> &[bool; 16] {
+        &self.keypad2
+    }
+
+    // the VIP's parallel port, for CHIP-8E: what FX03 last wrote, and a byte
+    // for FXE3/FXE7 to read, which also wakes FXE3
+    pub fn output(&self) -> u8 {
+        self.output
+    }
+
+    pub fn set_input(&mut self, byte: u8) {
+        self.input = byte;
+        self.input_strobe = true;
+    }
+
+    // Switches dialect and moves PC to where its programs start, so it has
+    // to come before loading the ROM.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.PC = self.start_address();
+        self.mem.resize(self.memory_size(), 0);
+        self.megachip = (platform == Platform::MegaChip).then(Box::default);
+        self.decoded.fill(None);
+        self.blocks = BlockCache::default();
+        self.display.mark_all_dirty(); // colours come and go
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn colours(&self) -> &ColourMap {
+        &self.colours
+    }
+
+    pub fn megachip(&self) -> Option<&MegaChip> {
+        self.megachip.as_deref()
+    }
+
+    pub fn megachip_mut(&mut self) -> Option<&mut MegaChip> {
+        self.megachip.as_deref_mut()
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        self.blocks = BlockCache::default(); // quirks are compiled into the blocks
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_stack_config(&mut self, config: StackConfig) -> Result<(), String> {
+        let slots = if config.in_memory { MEMORY_STACK_SLOTS } else { STATE_STACK_SLOTS };
+        if config.depth == 0 || config.depth > slots {
+            return Err(format!("Stack depth must be between 1 and {}: {}", slots, config.depth));
+        }
+        if self.stack.len() > config.depth {
+            return Err(format!("Stack already holds {} return addresses", self.stack.len()));
+        }
+        self.stack_config = config;
+        Ok(())
+    }
+
+    pub fn stack_config(&self) -> StackConfig {
+        self.stack_config
+    }
+
+    // return addresses, oldest first
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    // where entry `n` of a memory stack lives
+    fn stack_slot(n: usize) -> usize {
+        MEMORY_STACK_END - 2 * (n + 1)
+    }
+
+    fn push(&mut self, addr: usize) -> Result<(), String> {
+        if self.stack.len() == self.stack_config.depth {
+            return Err(format!("Stack overflow: 2NNN at 0x{:03X} nests deeper than {}", self.PC - 2, self.stack_config.depth));
+        }
+        if self.stack_config.in_memory {
+            let slot = Self::stack_slot(self.stack.len());
+            self.mem[slot..slot + 2].copy_from_slice(&(addr as u16).to_be_bytes());
+            self.written(slot, 2);
+        }
+        self.stack.push(addr);
+        Ok(())
+    }
+
+    // on a memory stack the address comes from RAM, wherever the ROM has left it
+    fn pop(&mut self) -> Result<usize, String> {
+        let Some(addr) = self.stack.pop() else {
+            return Err(format!("Stack underflow: 00EE at 0x{:03X} with nothing to return to", self.PC - 2));
+        };
+        if !self.stack_config.in_memory {
+            return Ok(addr);
+        }
+        let slot = Self::stack_slot(self.stack.len());
+        Ok(((self.mem[slot] as usize) << 8 | self.mem[slot + 1] as usize) & 0xFFF)
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    fn trace(&mut self, category: Category, message: impl FnOnce() -> String) {
+        if self.tracer.enabled() {
+            self.tracer.event(self.cycle, category, message);
+        }
+    }
+
+    // records `len` freshly written bytes from `addr` as a "cpu: write" event
+    fn trace_writes(&mut self, addr: usize, len: usize) {
+        if self.tracer.enabled() {
+            let bytes = &self.mem[addr..addr + len];
+            let message = || {
+                let writes: Vec<String> = bytes.iter().enumerate().map(|(n, b)| format!("{:03X}={:02X}", addr + n, b)).collect();
+                format!("write {}", writes.join(" "))
+            };
+            self.tracer.event(self.cycle, Category::Cpu, message);
+        }
+    }
+
+    // Fixed-size snapshot of the machine state (everything but quirks, which are
+    // configuration). The layout is private to `save_state`/`load_state`.
+    // MegaChip's 16MB of memory and its screens do not fit it.
+    pub fn save_state(&self) -> Result<Vec<u8>, String> {
+        if self.platform == Platform::MegaChip {
+            return Err("Save states are not supported on MegaChip".to_string());
+        }
+        if self.stack.len() > STATE_STACK_SLOTS {
+            return Err(format!("Stack too deep to save: {} entries", self.stack.len()));
+        }
+        let mut out = Vec::with_capacity(Self::state_size());
+        out.push(STATE_VERSION);
+        out.extend_from_slice(&self.mem);
+        out.extend_from_slice(&(self.PC as u16).to_le_bytes());
+        out.push(self.display.height() as u8);
+        out.extend(self.display.pixels().map(|px| px as u8));
+        out.resize(out.len() + WIDTH * (MAX_HEIGHT - self.display.height()), 0);
+        out.extend_from_slice(&(self.I as u16).to_le_bytes());
+        out.push(self.stack.len() as u8);
+        for slot in 0..STATE_STACK_SLOTS {
+            let addr = self.stack.get(slot).copied().unwrap_or(0) as u16;
+            out.extend_from_slice(&addr.to_le_bytes());
+        }
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.extend_from_slice(&self.register);
+        out.extend(self.keypad.iter().map(|&k| k as u8));
+        out.push(self.pressed_key.map_or(0xFF, |k| k));
+        out.extend(self.keypad2.iter().map(|&k| k as u8));
+        out.extend(self.colours.to_bytes());
+        out.extend_from_slice(&[self.delay_wait as u8, self.output, self.input, self.input_strobe as u8]);
+        Ok(out)
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != Self::state_size() || state[0] != STATE_VERSION {
+            return Err("Save state does not match this emulator version".to_string());
+        }
+        let mut pos = 1;
+        let mut take = |n: usize| {
+            pos += n;
+            &state[pos - n..pos]
+        };
+        if self.platform == Platform::MegaChip {
+            return Err("Save states are not supported on MegaChip".to_string());
+        }
+        self.mem.copy_from_slice(take(CODE_SIZE));
+        self.decoded.fill(None);
+        self.blocks = BlockCache::default();
+        let pc = take(2);
+        self.PC = u16::from_le_bytes([pc[0], pc[1]]) as usize;
+        let height = (take(1)[0] as usize).clamp(1, MAX_HEIGHT);
+        self.display = Framebuffer::new(height);
+        self.hires = height == HIRES_HEIGHT;
+        for (n, &b) in take(WIDTH * MAX_HEIGHT)[..WIDTH * height].iter().enumerate() {
+            self.display.set_pixel(n % WIDTH, n / WIDTH, b != 0);
+        }
+        let i = take(2);
+        self.I = u16::from_le_bytes([i[0], i[1]]) as u32;
+        let depth = take(1)[0] as usize;
+        let slots = take(STATE_STACK_SLOTS * 2);
+        self.stack = slots.chunks(2).take(depth).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).collect();
+        self.delay_timer = take(1)[0];
+        self.sound_timer = take(1)[0];
+        self.register.copy_from_slice(take(16));
+        for (k, &b) in self.keypad.iter_mut().zip(take(16)) {
+            *k = b != 0;
+        }
+        self.pressed_key = Some(take(1)[0]).filter(|&k| k < 16);
+        for (k, &b) in self.keypad2.iter_mut().zip(take(16)) {
+            *k = b != 0;
+        }
+        self.colours.load_bytes(take(ColourMap::STATE_SIZE));
+        let io = take(4);
+        (self.delay_wait, self.output, self.input, self.input_strobe) = (io[0] != 0, io[1], io[2], io[3] != 0);
+        self.display.mark_all_dirty();
+        self.display_flag = true;
+        Ok(())
+    }
+
+    pub fn state_size() -> usize {
+        1 + CODE_SIZE + 2 + 1 + WIDTH * MAX_HEIGHT + 2 + 1 + STATE_STACK_SLOTS * 2 + 2 + 16 + 16 + 1 + 16 + ColourMap::STATE_SIZE + 4
+    }
+
+    pub fn display(&self) -> &Framebuffer {
+        &self.display
+    }
+
+    // the instruction at PC, which `execute` will run next
+    pub fn fetch(&self) -> Result<u16, String> {
+        if self.PC + 1 >= self.mem.len() {
+            return Err(format!("PC 0x{:03X} is past the end of memory", self.PC));
+        }
+        Ok((self.mem[self.PC] as u16) << 8 | self.mem[self.PC + 1] as u16)
+    }
+
+    pub fn keypad(&self) -> &[bool; 16] {
+        &self.keypad
+    }
+
+    pub fn pc(&self) -> usize {
+        self.PC
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.register
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.register
+    }
+
+    pub fn index(&self) -> u32 {
+        self.I
+    }
+
+    pub fn set_index(&mut self, i: u32) {
+        self.I = i;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn read_mem(&self, addr: usize) -> u8 {
+        self.mem[addr]
+    }
+
+    pub fn write_mem(&mut self, addr: usize, val: u8) {
+        self.mem[addr] = val;
+        self.written(addr, 1);
+    }
+
+    // width and height of the picture `update_display_buffer` draws
+    pub fn output_size(&self) -> (usize, usize) {
+        match self.megachip {
+            Some(_) => (megachip::WIDTH, megachip::HEIGHT),
+            None => (WIDTH, self.display.height()),
+        }
+    }
+
+    // Converts the rows that changed since the last call, so `buffer` has to
+    // be the same one every time, sized for `output_size()`. CHIP-8X pixels
+    // take their colours from the colour map; everything else is white on black.
+    pub fn update_display_buffer(&mut self, buffer: &mut [u32]){
+        let (width, height) = self.output_size();
+        assert_eq!(buffer.len(), width * height, "display buffer size");
+        if self.megachip.is_some() {
+            self.update_megachip_buffer(buffer);
+            self.display_flag = false;
+            return;
+        }
+        let dirty = self.display.take_dirty();
+        let colour = self.platform == Platform::Chip8X;
+        for y in (0..self.display.height()).filter(|y| dirty >> y & 1 != 0) {
+            for x in 0..WIDTH {
+                buffer[y * WIDTH + x] = match (self.display.pixel(x, y), colour) {
+                    (true, true) => self.colours.foreground(x, y),
+                    (false, true) => self.colours.background(),
+                    (true, false) => 0xFFFFFF,
+                    (false, false) => 0x0,
+                };
             }
         }
         self.display_flag = false;
-        // window.
     }
 
-    // fetch-increment-execute loop
-    pub fn execute(&mut self) -> Result<(), String>{
-        let instr: u16 = (self.mem[self.PC] as u16) << 8 | self.mem[self.PC + 1] as u16;
-        self.PC += 2;
-
-        print!("PC: 0x{:04X} Executing: 0x{:04X}", self.PC, instr);
-        match (instr & 0xF000) >> 12 {
-            0 => match instr & 0x00FF { 
-                0x00E0 => { // clear screen
-                    self.display = [[false; 64]; 32];
-                    self.display_flag = true;
-                    println!(" clr");
-                }
-                0x00EE => { // pop subroutine
-                    let link = self.stack.pop().unwrap();
-                    self.PC = link.into();
-                    println!(" retn");
+    // The MegaChip platform always shows 256x192. In MegaChip mode that is the
+    // last presented picture; otherwise the CHIP-8 display, each pixel 4x4,
+    // in the middle.
+    fn update_megachip_buffer(&mut self, buffer: &mut [u32]) {
+        const SCALE: usize = megachip::WIDTH / WIDTH;
+        let mega = self.megachip.as_deref_mut().unwrap();
+        let redraw = mega.take_presented();
+        let dirty = self.display.take_dirty();
+        if mega.enabled() {
+            if redraw {
+                for (n, px) in buffer.iter_mut().enumerate() {
+                    *px = mega.pixel(n % megachip::WIDTH, n / megachip::WIDTH);
                 }
-                _ => {
-                    return Err(format!("Instruction cannot be matched: 0x{:04X}", instr));
+            }
+            return;
+        }
+        if redraw {
+            buffer.fill(0);
+        }
+        let top = (megachip::HEIGHT - self.display.height() * SCALE) / 2;
+        for y in (0..self.display.height()).filter(|y| redraw || dirty >> y & 1 != 0) {
+            for x in 0..WIDTH {
+                let colour = if self.display.pixel(x, y) { 0xFFFFFF } else { 0x0 };
+                for line in top + y * SCALE..top + (y + 1) * SCALE {
+                    let at = line * megachip::WIDTH + x * SCALE;
+                    buffer[at..at + SCALE].fill(colour);
                 }
             }
-            1 => { // 1NNN jump
-                self.PC = (instr & 0x0FFF) as usize; // set the PC to the last 3 bits
-                println!(" jmp 0x{:04X}", self.PC);
+        }
+    }
+
+    // The decoded instruction at PC, from the cache when it has been seen
+    // before. The cache covers CODE_SIZE, so MegaChip code past that is decoded
+    // every time.
+    fn fetch_op(&mut self) -> Result<(u16, Op), String> {
+        let instr = self.fetch()?;
+        if !self.decode_cache || self.PC >= self.decoded.len() {
+            return Ok((instr, decode_for(self.platform, instr)));
+        }
+        match self.decoded[self.PC] {
+            Some(op) => Ok((instr, op)),
+            None => {
+                let op = decode_for(self.platform, instr);
+                self.decoded[self.PC] = Some(op);
+                Ok((instr, op))
             }
-            2 => { // 2NNN JALR
-                self.stack.push(self.PC);
-                self.PC = (instr & 0x0FFF) as usize; // set the PC to the last 3 bits
-                println!(" jalr 0x{:04X}", self.PC);
+        }
+    }
+
+    // the address in I, if `len` bytes from there are all in memory
+    fn span(&self, len: usize) -> Result<usize, String> {
+        let i = self.I as usize;
+        if i + len > self.mem.len() {
+            return Err(format!("I 0x{:03X} + {} is past the end of memory", i, len));
+        }
+        Ok(i)
+    }
+
+    // every write to memory ends up here
+    fn written(&mut self, addr: usize, len: usize) {
+        self.invalidate(addr, len);
+        if self.memory_display && addr + len > DISPLAY_MEM {
+            self.mem_to_display(addr.max(DISPLAY_MEM), addr + len);
+        }
+    }
+
+    // On the COSMAC VIP the display is the top 256 bytes of RAM. With this on,
+    // the display is copied there after every 00E0 and DXYN, and memory writes
+    // there show up on the display.
+    pub fn set_memory_display(&mut self, enabled: bool) {
+        self.memory_display = enabled;
+        self.blocks = BlockCache::default(); // drawing ends blocks with it on
+        if enabled {
+            self.display_to_mem();
+        }
+    }
+
+    pub fn memory_display(&self) -> bool {
+        self.memory_display
+    }
+
+    // Lets 0NNN call CDP1802 machine code, as in VIP "hybrid" programs.
+    // Otherwise 0NNN is an error, since it is far more often a stray jump
+    // into data than a subroutine.
+    pub fn set_hybrid(&mut self, enabled: bool) {
+        self.hybrid = enabled;
+    }
+
+    pub fn hybrid(&self) -> bool {
+        self.hybrid
+    }
+
+    // Runs the machine code at `addr` until it returns with D4 (SEP R4). The
+    // 1802 sees what the VIP interpreter would leave it: V0-VF at 0xEF0, the
+    // display at 0xF00, R2 the stack pointer, R3 the program counter, R5 the
+    // CHIP-8 PC, R6/R7 pointing at VX/VY, R8 the timers, RA = I and RB.1 the
+    // display page. What it leaves there comes back into the CHIP-8 machine.
+    fn call_machine_code(&mut self, addr: u16) -> Result<(), String> {
+        self.mem[VIP_REGISTERS..VIP_REGISTERS + 16].copy_from_slice(&self.register);
+        self.invalidate(VIP_REGISTERS, 16);
+        self.display_to_mem();
+
+        let mut core = Cdp1802::new();
+        let (x, y) = ((addr >> 8) & 0xF, (addr >> 4) & 0xF);
+        core.r[2] = (MEMORY_STACK_END - 1 - 2 * self.stack.len()) as u16;
+        core.r[3] = addr;
+        core.r[5] = self.PC as u16;
+        core.r[6] = VIP_REGISTERS as u16 + x;
+        core.r[7] = VIP_REGISTERS as u16 + y;
+        core.r[8] = (self.delay_timer as u16) << 8 | self.sound_timer as u16;
+        core.r[0xA] = self.I as u16;
+        core.r[0xB] = DISPLAY_MEM as u16;
+        core.p = 3;
+        core.x = 2;
+        let mut bus = HybridBus { mem: &mut self.mem[..CODE_SIZE], keypad: self.keypad, key: 0, ef1: false, written: None };
+        let mut steps = 0;
+        while core.p != 4 {
+            if steps == MAX_MACHINE_INSTRUCTIONS {
+                return Err(format!("Machine code at 0x{:03X} did not return within {} instructions", addr, steps));
             }
-            3 => { // 3XNN beq
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                let a = self.register[vx];
-                let b = instr & 0x00FF;
-                if a == b as u8 {
+            core.step(&mut bus)?;
+            core.idle = false; // IDL waits for the next interrupt, which would come and go
+            steps += 1;
+        }
+        let written = bus.written;
+
+        self.register.copy_from_slice(&self.mem[VIP_REGISTERS..VIP_REGISTERS + 16]);
+        self.I = (core.r[0xA] & 0xFFF) as u32;
+        self.PC = core.r[5] as usize & 0xFFF;
+        self.delay_timer = (core.r[8] >> 8) as u8;
+        self.sound_timer = core.r[8] as u8;
+        if let Some((start, end)) = written {
+            self.written(start, end - start);
+        }
+        self.mem_to_display(DISPLAY_MEM, CODE_SIZE);
+        self.trace(Category::Cpu, || format!("machine code at {:03X} returned after {} instructions", addr, steps));
+        Ok(())
+    }
+
+    fn clear_display(&mut self) {
+        self.display.clear();
+        if self.memory_display {
+            self.display_to_mem();
+        }
+        self.display_flag = true;
+        self.trace(Category::Draw, || "clear".to_string());
+    }
+
+    // the whole picture has to be converted again
+    fn colours_changed(&mut self) {
+        self.display.mark_all_dirty();
+        self.display_flag = true;
+        let background = self.colours.background();
+        self.trace(Category::Draw, || format!("colours background={:06X}", background));
+    }
+
+    // only the top 32 rows of a HIRES display fit
+    fn display_to_mem(&mut self) {
+        for (y, row) in self.display.rows().iter().take(HEIGHT).enumerate() {
+            let at = DISPLAY_MEM + y * WIDTH / 8;
+            self.mem[at..at + WIDTH / 8].copy_from_slice(&row.to_be_bytes());
+        }
+        self.invalidate(DISPLAY_MEM, HEIGHT * WIDTH / 8);
+    }
+
+    // updates the display rows overlapping the bytes start..end of its memory
+    fn mem_to_display(&mut self, start: usize, end: usize) {
+        let bytes = WIDTH / 8;
+        for y in (start - DISPLAY_MEM) / bytes..(end - DISPLAY_MEM).div_ceil(bytes).min(HEIGHT) {
+            let at = DISPLAY_MEM + y * bytes;
+            let row = u64::from_be_bytes(self.mem[at..at + bytes].try_into().unwrap());
+            self.display.set_row(y, row);
+        }
+        self.display_flag = true;
+    }
+
+    // drops cached decodes of any instruction overlapping `len` bytes written at `addr`
+    fn invalidate(&mut self, addr: usize, len: usize) {
+        let end = (addr + len).min(self.decoded.len());
+        self.decoded[addr.saturating_sub(1).min(end)..end].fill(None);
+        if self.jit {
+            let (start, end) = self.code_written.unwrap_or((addr, addr + len));
+            self.code_written = Some((start.min(addr), end.max(addr + len)));
+        }
+    }
+
+    // Decoding every instruction afresh is slower but keeps nothing between
+    // steps; the two must always behave the same.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled;
+        self.decoded.fill(None);
+    }
+
+    // fetch-increment-execute loop
+    pub fn execute(&mut self) -> Result<(), String>{
+        let (instr, op) = self.fetch_op()?;
+        if self.tracer.enabled() {
+            self.tracer.instr(self.cycle, self.PC as u16, instr, self.I as u16, self.register); // traces keep to 16 bits
+        }
+        self.execute_at(self.PC, op)
+    }
+
+    // What `execute` does with `op` once it has been fetched from `pc` and
+    // decoded, for code that decodes ahead of time (see recompile.rs).
+    pub fn execute_at(&mut self, pc: usize, op: Op) -> Result<(), String> {
+        self.PC = pc + 2;
+        self.exec_op(op)?;
+        self.cycle += 1;
+        Ok(())
+    }
+
+    // Moves PC to `pc` and counts `instructions` as executed, for code that
+    // ran them itself.
+    pub fn advance(&mut self, pc: usize, instructions: u64) {
+        self.PC = pc;
+        self.cycle += instructions;
+    }
+
+    // Runs an already fetched instruction, with PC already past it. Shared by
+    // `execute` and the compiled blocks in jit.rs.
+    pub(crate) fn exec_op(&mut self, op: Op) -> Result<(), String> {
+        match op {
+            Op::Clear if self.mega_mode() => { // MegaChip: show what was drawn and start again
+                self.megachip.as_deref_mut().unwrap().present();
+                self.display_flag = true;
+            }
+            Op::Clear => self.clear_display(), // clear screen
+            Op::MachineCall(HIRES_CLEAR) if self.hires => self.clear_display(),
+            Op::Return => { // pop subroutine
+                self.PC = self.pop()?;
+            }
+            Op::MachineCall(nnn) if self.hybrid => self.call_machine_code(nnn)?,
+            Op::MachineCall(nnn) => {
+                return Err(format!("Instruction cannot be matched: 0x{:04X}", nnn));
+            }
+            Op::Jump(nnn) => { // 1NNN jump
+                self.PC = nnn as usize;
+            }
+            Op::Call(nnn) => { // 2NNN JALR
+                self.push(self.PC)?;
+                self.PC = nnn as usize;
+            }
+            Op::SkipEqImm(x, nn) => { // 3XNN beq
+                if self.register[x as usize] == nn {
                     self.PC += 2;
                 }
-                println!(" beq 0x{:01X}, 0x{:02X}", vx, b);
             }
-            4 => { // 4XNN bne
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                let a = self.register[vx];
-                let b = instr & 0x00FF;
-                if a != b as u8 {
+            Op::SkipNeImm(x, nn) => { // 4XNN bne
+                if self.register[x as usize] != nn {
                     self.PC += 2;
                 }
-                println!(" bne 0x{:01X}, 0x{:02X}", vx, b);
-            }
-            5 => { // 5XY0
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                let vy = ((instr & 0x00F0) >> 4) as usize;
-                let a = self.register[vx];
-                let b = self.register[vy];
-                if a == b {
+            }
+            Op::SkipEq(x, y) => { // 5XY0
+                if self.register[x as usize] == self.register[y as usize] {
                     self.PC += 2;
                 }
-                println!(" ber 0x{:01X}, 0x{:01X}", vx, vy);
-            }
-            6 => { // 6XNN set register VX
-                self.register[((instr & 0x0F00) >> 8) as usize] = (instr & 0x00FF) as u8;
-                println!(" set 0x{:01X}, 0x{:02X}", ((instr & 0x0F00) >> 8), (instr & 0x00FF)); 
-            }
-            7 => { // 7XNN add value to register
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                self.register[vx] = self.register[vx].wrapping_add((instr & 0x00FF) as u8);
-                println!(" addi 0x{:01X}, 0x{:02X}", ((instr & 0x0F00) >> 8), (instr & 0x00FF)); 
-            }
-            8 => {
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                let vy = ((instr & 0x00F0) >> 4) as usize;
-                match instr & 0x000F { // 8XYn -- arithmetic / logical operations
-                    0 => { // set
-                        self.register[vx] = self.register[vy];
-                        println!(" set 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    1 => { //OR
-                        self.register[vx] |= self.register[vy];
-                        println!(" or 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    2 => { // and
-                        self.register[vx] &= self.register[vy];
-                        println!(" and 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    3 => {
-                        self.register[vx] ^= self.register[vy];
-                        println!(" xor 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    4 => {
-                        let val= self.register[vx] as u16 + self.register[vy] as u16;
-                        self.register[vx] = (val & 0xFF) as u8;
-                        self.register[0xF] = if val > 0xFF {1} else {0};
-                        // let (sum, carry) = self.register[vx].overflowing_add(self.register[vy]);
-                        // self.register[vx] = sum;
-                        // self.register[0xF] = if carry { 1 } else { 0 };
-                        println!(" add 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    5 => {
-                        let (result, did_borrow) = self.register[vx].overflowing_sub(self.register[vy]);
-                        self.register[vx] = result;
-                        self.register[0xF] = if did_borrow { 0 } else { 1 };
-                        println!(" sub 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    7 => {
-                        let (result, did_borrow) = self.register[vy].overflowing_sub(self.register[vx]);
-                        self.register[vx] = result;
-                        self.register[0xF] = if did_borrow { 0 } else { 1 };
-                        println!(" rsub 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    6 => {
-                        let shifted = self.register[vy] & 1;
-                        self.register[vx] = self.register[vy] >> 1;
-                        self.register[0xF] = shifted;
-                        println!(" str 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    0xE => {
-                        let shifted = (self.register[vy] & 0b10000000) >> 7;
-                        self.register[vx] = self.register[vy] << 1; 
-                        self.register[0xF] = shifted;
-                        println!(" stl 0x{:01X}, 0x{:01X}", vx, vy); 
-                    }
-                    _ => {
-                        return Err(format!("Instruction cannot be matched: 0x{:04X}", instr));
-                    }
+            }
+            Op::SetImm(x, nn) => { // 6XNN set register VX
+                self.register[x as usize] = nn;
+            }
+            Op::AddImm(x, nn) => { // 7XNN add value to register
+                let vx = x as usize;
+                self.register[vx] = self.register[vx].wrapping_add(nn);
+            }
+            // 8XYn -- arithmetic / logical operations
+            Op::Set(x, y) => {
+                self.register[x as usize] = self.register[y as usize];
+            }
+            Op::Or(x, y) => {
+                self.register[x as usize] |= self.register[y as usize];
+                if self.quirks.vf_reset {
+                    self.register[0xF] = 0;
                 }
             }
-            9 => { // 5XY0
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                let vy = ((instr & 0x00F0) >> 4) as usize;
-                let a = self.register[vx];
-                let b = self.register[vy];
-                if a != b as u8 {
+            Op::And(x, y) => {
+                self.register[x as usize] &= self.register[y as usize];
+                if self.quirks.vf_reset {
+                    self.register[0xF] = 0;
+                }
+            }
+            Op::Xor(x, y) => {
+                self.register[x as usize] ^= self.register[y as usize];
+                if self.quirks.vf_reset {
+                    self.register[0xF] = 0;
+                }
+            }
+            Op::Add(x, y) => {
+                let (vx, vy) = (x as usize, y as usize);
+                let val = self.register[vx] as u16 + self.register[vy] as u16;
+                self.register[vx] = (val & 0xFF) as u8;
+                self.register[0xF] = if val > 0xFF {1} else {0};
+            }
+            Op::Sub(x, y) => {
+                let (vx, vy) = (x as usize, y as usize);
+                let (result, did_borrow) = self.register[vx].overflowing_sub(self.register[vy]);
+                self.register[vx] = result;
+                self.register[0xF] = if did_borrow { 0 } else { 1 };
+            }
+            Op::SubReverse(x, y) => {
+                let (vx, vy) = (x as usize, y as usize);
+                let (result, did_borrow) = self.register[vy].overflowing_sub(self.register[vx]);
+                self.register[vx] = result;
+                self.register[0xF] = if did_borrow { 0 } else { 1 };
+            }
+            Op::ShiftRight(x, y) => {
+                let src = if self.quirks.shift_vx { x } else { y } as usize;
+                let shifted = self.register[src] & 1;
+                self.register[x as usize] = self.register[src] >> 1;
+                self.register[0xF] = shifted;
+            }
+            Op::ShiftLeft(x, y) => {
+                let src = if self.quirks.shift_vx { x } else { y } as usize;
+                let shifted = (self.register[src] & 0b10000000) >> 7;
+                self.register[x as usize] = self.register[src] << 1;
+                self.register[0xF] = shifted;
+            }
+            Op::SkipNe(x, y) => { // 9XY0
+                if self.register[x as usize] != self.register[y as usize] {
                     self.PC += 2;
                 }
-                println!(" bnr 0x{:01X}, 0x{:01X}", vx, vy);
-            }
-            0xA => { // ANNN set index register I
-                self.I = (instr & 0x0FFF) as u16;
-                println!(" seti 0x{:03X}", (instr & 0x0FFF)); 
-            }
-            0xB => { // BNNN Jump with offset in V0
-                self.PC = (instr & 0x0FFF) as usize + self.register[0] as usize;
-                println!(" jwo 0x{:03X}", (instr & 0x0FFF)); 
-            }
-            0xC => { // CXNN rnd & NN
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                let rnd = rand::random::<u16>() & instr & 0x00FF;
-                self.register[vx] = rnd as u8;
-                println!(" rnd 0x{:X}, 0x{:02X}", vx, (instr & 0x00FF));
-            }
-            0xD => { // DXYN display / draw
-                let x = self.register[((instr & 0x0F00) >> 8) as usize] & 63;
-                let y: u16 = (self.register[((instr & 0x00F0) >> 4) as usize] & 31) as u16;
-                let n = (instr & 0x000F);
-                println!(" draw 0x{:01X}, 0x{:01X}, 0x{:01X}", x, y, n);
-                self.register[0xF as usize] = 0;
+            }
+            Op::SetI(nnn) => { // ANNN set index register I
+                self.I = nnn as u32;
+            }
+            Op::JumpOffset(x, nnn) => { // BNNN Jump with offset in V0
+                let offset = if self.quirks.jump_vx { x as usize } else { 0 };
+                self.PC = nnn as usize + self.register[offset] as usize;
+            }
+            Op::Random(x, nn) => { // CXNN rnd & NN
+                self.register[x as usize] = rand::random::<u8>() & nn;
+            }
+            Op::Draw(x, y, _) if self.mega_mode() => { // DXYN: a palette sprite, sized by 03NN/04NN
+                let (x, y) = (self.register[x as usize] as usize, self.register[y as usize] as usize);
+                let mega = self.megachip.as_deref_mut().unwrap();
+                let start = (self.I as usize).min(self.mem.len());
+                let end = (start + mega.sprite_len()).min(self.mem.len());
+                self.register[0xF] = mega.draw(&self.mem[start..end], x, y) as u8;
+                self.display_flag = true;
+                let (i, vf) = (self.I, self.register[0xF]);
+                self.trace(Category::Draw, || format!("sprite x={} y={} i={:06X} vf={}", x, y, i, vf));
+            }
+            Op::Draw(x, y, n) => { // DXYN display / draw
+                let height = self.display.height() as u16;
+                let x = self.register[x as usize] & 63;
+                let y: u16 = self.register[y as usize] as u16 % height;
+                let n = n as u16;
+                let start = self.span(n as usize)?;
+                self.register[0xF] = 0;
                 for i in 0..n {
-                    let byte = self.mem[(self.I + i as u16) as usize];
-                    print!("0x{:02X} ", byte);
-                    if y + i >= 32 {
+                    let byte = self.mem[start + i as usize];
+                    if y + i >= height && !self.quirks.wrap_sprites {
                         break;
                     }
-                    for j in 0..8{
-                        if x + j >= 64 {
-                            break;
-                        }
-                        let cur = self.display[(y + i) as usize][(x + j) as usize];
-                        // let new = (byte & (1<<j)) != 0;
-                        let new = (byte & (0x80 >> j)) != 0; // 0x80 = 0b10000000
-                        if cur && new {
-                            self.register[0xF as usize] = 1;
-                        }
-                        self.display[(y + i) as usize][(x + j) as usize] = cur ^ new; 
+                    let row = ((y + i) % height) as usize;
+                    if self.display.draw_byte(x as usize, row, byte, self.quirks.wrap_sprites) {
+                        self.register[0xF] = 1;
                     }
                 }
-                    println!();
+                if self.memory_display {
+                    self.display_to_mem();
+                }
                 self.display_flag = true;
+                let (i, vf) = (self.I, self.register[0xF]);
+                self.trace(Category::Draw, || format!("sprite x={} y={} n={} i={:03X} vf={}", x, y, n, i, vf));
             }
-            0xE => { // 
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                let keycode = self.register[vx];
-                println!(" keycode: 0x{:X}  ", keycode);
-                match instr & 0x00FF {
-                    0x9E => { // skip if pressed
-                        if let Some(qwerty_key) = get_qwerty_key(keycode) {
-                            if self.window.is_key_down(qwerty_key) {
-                                self.PC += 2;
-                                println!("GOOD");
-                            }
-                        } else {
-                            println!("Invalid CHIP-8 keycode in VX: {}", keycode);
-                        }
-                        println!(" skp 0x{:X}", vx);
-                    }
-                    0xA1 => {
-                        if let Some(qwerty_key) = get_qwerty_key(keycode) {
-                            if !self.window.is_key_down(qwerty_key) {
-                                self.PC += 2;
-                                println!("GOOD");
-                            }
-                        } else {
-                            println!("Invalid CHIP-8 keycode in VX: {}", keycode);
-                        }
-                        println!(" snp 0x{:X}", vx);
-                        // if vx == 0 {
-                        //     return Err("monkey".to_string());
-                        // }
-                    }
-                    _ => {
-                        return Err(format!("Instruction cannot be matched: 0x{:04X}", instr));
+            Op::SkipKey(vx) => { // skip if pressed
+                let keycode = self.register[vx as usize];
+                if keycode < 16 {
+                    if self.keypad[keycode as usize] {
+                        self.PC += 2;
+                        self.trace(Category::Input, || format!("key {:X} down", keycode));
                     }
+                } else {
+                    self.trace(Category::Input, || format!("invalid keycode in V{:X}: {}", vx, keycode));
                 }
             }
-            0xF => {
-                let vx = ((instr & 0x0F00) >> 8) as usize;
-                match instr & 0x00FF {
-                    0x07 => {
-                        self.register[vx] = self.delay_timer;
-                        println!(" ldt 0x{:X}", vx);
-                    }
-                    0x15 => {
-                        self.delay_timer = self.register[vx];
-                        println!(" sdt 0x{:X}", vx);
-                    }
-                    0x18 => {
-                        self.sound_timer = self.register[vx];
-                        println!(" sst 0x{:X}", vx);
-                    }
-                    0x1E => {
-                        self.I += self.register[vx] as u16;
-                        println!(" addI 0x{:X}", vx);
-                    }
-                    0x0A => { // get key
-                        let keys = self.window.get_keys_pressed(minifb::KeyRepeat::No);
-                        if let Some(&key) = keys.first() {
-                            println!("Detected keypress {:?}", key);
-                            if let Some(chip8_key) = get_chip8_key(key) {
-                                self.register[vx] = chip8_key;
-                            } else {
-                                self.PC -= 2;
-                            }
-                        } else {
-                            self.PC -= 2;
-                        }
-                    }
-                    0x29 => { // font character
-                        self.I = (FONT_START + self.register[vx] as usize * 5) as u16;
-                        println!(" font 0x{:X}", vx);
-                    }
-                    0x33 => { // decimal division -- stores from I, I + 1, I + 2, in little endian
-                        let val = self.register[vx];
-                        self.mem[self.I as usize] = val / 100;
-                        self.mem[self.I as usize + 1] = (val % 100) / 10;
-                        self.mem[self.I as usize + 2] = val % 10;
-                        println!(" bcd 0x{:X}", vx);
-                    }
-                    0x55 => { // store memory
-                        for i in 0..=vx {
-                            self.mem[self.I as usize + i] = self.register[i]
-                        }
-                        println!(" store 0x{:X}", vx);
-                    }
-                    0x65 => { // load memory
-                        for i in 0..=vx {
-                            self.register[i] = self.mem[self.I as usize + i]
-                        }
-                        println!(" load 0x{:X}", vx);
-                    }
-                    _ => {
-                        return Err(format!("Instruction cannot be matched: 0x{:04X}", instr));
+            Op::SkipNoKey(vx) => {
+                let keycode = self.register[vx as usize];
+                if keycode < 16 {
+                    if !self.keypad[keycode as usize] {
+                        self.PC += 2;
+                    } else {
+                        self.trace(Category::Input, || format!("key {:X} down", keycode));
                     }
+                } else {
+                    self.trace(Category::Input, || format!("invalid keycode in V{:X}: {}", vx, keycode));
                 }
             }
-            _ => {
+            Op::GetDelay(x) => {
+                self.register[x as usize] = self.delay_timer;
+            }
+            Op::SetDelay(x) => {
+                self.delay_timer = self.register[x as usize];
+                let t = self.delay_timer;
+                self.trace(Category::Timers, || format!("delay={}", t));
+            }
+            Op::SetSound(x) => {
+                self.sound_timer = self.register[x as usize];
+                let t = self.sound_timer;
+                self.trace(Category::Timers, || format!("sound={}", t));
+            }
+            Op::AddI(x) => {
+                self.I += self.register[x as usize] as u32;
+            }
+            Op::WaitKey(x) => { // get key
+                if let Some(chip8_key) = self.pressed_key.take() {
+                    self.register[x as usize] = chip8_key;
+                    self.trace(Category::Input, || format!("key {:X} pressed", chip8_key));
+                } else {
+                    self.PC -= 2;
+                }
+            }
+            Op::Font(x) => { // font character
+                self.I = (self.profile.font_start + self.register[x as usize] as usize * 5) as u32;
+            }
+            Op::BigFont(x) => { // SCHIP's 8x10 digits, stored after the small ones
+                if self.profile.font.big.is_none() {
+                    return Err(format!("Instruction cannot be matched: 0x{:04X} (the font has no big digits)", 0xF030 | (x as u16) << 8));
+                }
+                let digit = (self.register[x as usize] & 0xF) as usize;
+                self.I = (self.profile.font_start + font::SMALL_SIZE + digit * 10) as u32;
+            }
+            Op::Bcd(x) => { // decimal division -- stores from I, I + 1, I + 2, in little endian
+                let val = self.register[x as usize];
+                let start = self.span(3)?;
+                self.mem[start] = val / 100;
+                self.mem[start + 1] = (val % 100) / 10;
+                self.mem[start + 2] = val % 10;
+                self.written(start, 3);
+                self.trace_writes(start, 3);
+            }
+            Op::Store(x) => { // store memory
+                let vx = x as usize;
+                let start = self.span(vx + 1)?;
+                self.mem[start..=start + vx].copy_from_slice(&self.register[..=vx]);
+                self.written(start, vx + 1);
+                self.trace_writes(start, vx + 1);
+                if self.quirks.increment_i {
+                    self.I += vx as u32 + 1;
+                }
+            }
+            Op::Load(x) => { // load memory
+                let vx = x as usize;
+                let start = self.span(vx + 1)?;
+                self.register[..=vx].copy_from_slice(&self.mem[start..=start + vx]);
+                if self.quirks.increment_i {
+                    self.I += vx as u32 + 1;
+                }
+            }
+            Op::CycleBackground => {
+                self.colours.cycle_background();
+                self.colours_changed();
+            }
+            Op::AddNibbles(x, y) => { // 5XY1: each nibble separately, modulo 8, for zone coordinates
+                let (vx, vy) = (self.register[x as usize], self.register[y as usize]);
+                let high = ((vx >> 4) + (vy >> 4)) & 7;
+                let low = ((vx & 0xF) + (vy & 0xF)) & 7;
+                self.register[x as usize] = high << 4 | low;
+            }
+            Op::ZoneColour(x, y) => { // BXY0: zones from VX and VX+1, colour from VY
+                let (vx, vx1) = (self.register[x as usize], self.register[(x as usize + 1) & 0xF]);
+                self.colours.set_zones(vx, vx1, self.register[y as usize]);
+                self.colours_changed();
+            }
+            Op::SpriteColour(x, y, n) => { // BXYN: the sprite at VX, VX+1, colour from VY
+                let (vx, vx1) = (self.register[x as usize], self.register[(x as usize + 1) & 0xF]);
+                self.colours.set_area(vx, vx1, n, self.register[y as usize]);
+                self.colours_changed();
+            }
+            Op::SkipKey2(vx) => {
+                if self.keypad2[(self.register[vx as usize] & 0xF) as usize] {
+                    self.PC += 2;
+                }
+            }
+            Op::SkipNoKey2(vx) => {
+                if !self.keypad2[(self.register[vx as usize] & 0xF) as usize] {
+                    self.PC += 2;
+                }
+            }
+            Op::MegaOff | Op::MegaOn => {
+                self.mega()?.set_enabled(op == Op::MegaOn);
+                self.display.mark_all_dirty();
+                self.display_flag = true;
+            }
+            Op::LongIndex(nn) => { // 01NN NNNN: I = NNNNNN, from this word and the next
+                let low = (self.mem[self.PC] as u32) << 8 | self.mem[self.PC + 1] as u32;
+                self.I = (nn as u32) << 16 | low;
+                self.PC += 2;
+            }
+            Op::LoadPalette(nn) => {
+                let start = (self.I as usize).min(self.mem.len());
+                let mega = self.megachip.as_deref_mut().ok_or_else(not_megachip)?;
+                mega.load_palette(&self.mem[start..], nn);
+            }
+            Op::SpriteWidth(nn) => self.mega()?.set_sprite_width(nn),
+            Op::SpriteHeight(nn) => self.mega()?.set_sprite_height(nn),
+            Op::ScreenAlpha(nn) => {
+                self.mega()?.set_alpha(nn);
+                self.display_flag = true;
+            }
+            Op::PlaySample(n) => { // 060N: N = 0 loops
+                let start = (self.I as usize).min(self.mem.len());
+                let mega = self.megachip.as_deref_mut().ok_or_else(not_megachip)?;
+                mega.play(&self.mem[start..], n == 0);
+            }
+            Op::StopSample => self.mega()?.stop(),
+            Op::BlendMode(n) => self.mega()?.set_blend(Blend::from_mode(n)),
+            Op::CollisionColour(nn) => self.mega()?.set_collision(nn),
+            Op::Stop => { // 00ED: went back to the monitor; here it just stays put
+                self.PC -= 2;
+            }
+            Op::NoOp => {}
+            Op::WaitDelay => { // 0151
+                if self.delay_timer > 0 {
+                    self.PC -= 2;
+                }
+            }
+            Op::SetDelayWait(x) => { // FX4F: FX15 and 0151 in one
+                if !self.delay_wait {
+                    self.delay_timer = self.register[x as usize];
+                    let t = self.delay_timer;
+                    self.trace(Category::Timers, || format!("delay={}", t));
+                }
+                self.delay_wait = self.delay_timer > 0;
+                if self.delay_wait {
+                    self.PC -= 2;
+                }
+            }
+            Op::SkipNext => { // 0188
+                self.PC += 2;
+            }
+            Op::SkipGreater(x, y) => { // 5XY1
+                if self.register[x as usize] > self.register[y as usize] {
+                    self.PC += 2;
+                }
+            }
+            Op::StoreRange(x, y) => { // 5XY2: VX to VY from I, leaving I past them
+                let range = x as usize..=y as usize;
+                let len = range.clone().count();
+                let i = self.span(len)?;
+                for (n, v) in range.enumerate() {
+                    self.mem[i + n] = self.register[v];
+                }
+                self.written(i, len);
+                self.trace_writes(i, len);
+                self.I += len as u32;
+            }
+            Op::LoadRange(x, y) => { // 5XY3
+                let range = x as usize..=y as usize;
+                let len = range.clone().count();
+                let i = self.span(len)?;
+                for (n, v) in range.enumerate() {
+                    self.register[v] = self.mem[i + n];
+                }
+                self.I += len as u32;
+            }
+            Op::BranchBack(nn) => { // BBNN: NN bytes back from the next instruction
+                self.PC = self.PC.wrapping_sub(nn as usize) & 0xFFF;
+            }
+            Op::BranchForward(nn) => { // BFNN
+                self.PC = (self.PC + nn as usize) & 0xFFF;
+            }
+            Op::SkipBytes(x) => { // FX1B
+                self.PC = (self.PC + self.register[x as usize] as usize) & 0xFFF;
+            }
+            Op::Output(x) => { // FX03: to the output port
+                self.output = self.register[x as usize];
+                let byte = self.output;
+                self.trace(Category::Input, || format!("output {:02X}", byte));
+            }
+            Op::InputWait(x) => { // FXE3: wait for the input strobe, then read the port
+                if self.input_strobe {
+                    self.input_strobe = false;
+                    self.register[x as usize] = self.input;
+                } else {
+                    self.PC -= 2;
+                }
+            }
+            Op::Input(x) => { // FXE7: read the port without waiting
+                self.register[x as usize] = self.input;
+            }
+            Op::Invalid(instr) => {
                 return Err(format!("Instruction cannot be matched: 0x{:04X}", instr));
             }
-        } 
+        }
         Ok(())
     }
-}
\ No newline at end of file
+
+    // the MegaChip state, for MegaChip instructions
+    fn mega(&mut self) -> Result<&mut MegaChip, String> {
+        self.megachip.as_deref_mut().ok_or_else(not_megachip)
+    }
+
+    // whether 00E0 and DXYN work on the true-colour screen
+    fn mega_mode(&self) -> bool {
+        self.megachip.as_ref().is_some_and(|mega| mega.enabled())
+    }
+}
+
+// A HIRES program starts with 1260 and clears its 64x64 screen with 0230
+// somewhere after the patch. Plenty of plain programs start by jumping over
+// data to 0x260, but they have no reason to call machine code at 0x230.
+fn is_hires(rom: &[u8]) -> bool {
+    let code = rom.get(HIRES_START - 0x200..).unwrap_or_default();
+    rom.starts_with(&HIRES_SIGNATURE) && code.chunks_exact(2).any(|w| w == HIRES_CLEAR.to_be_bytes())
+}
+
+fn not_megachip() -> String {
+    "MegaChip instruction on another platform".to_string()
+}
+
+// What 0NNN machine code sees: CHIP-8 memory, mirrored through the 1802's
+// 64K address space, and the VIP keypad, where OUT 2 selects a key and EF3
+// says whether it is down. Nothing drives EF1, the 1861's display status, so
+// it flips on every read to let loops waiting for either edge finish.
+struct HybridBus<'a> {
+    mem: &'a mut [u8],
+    keypad: [bool; 16],
+    key: u8,
+    ef1: bool,
+    written: Option<(usize, usize)>, // bytes written, as start..end
+}
+
+impl cdp1802::Bus for HybridBus<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.mem[addr as usize & 0xFFF]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        let addr = addr as usize & 0xFFF;
+        self.mem[addr] = val;
+        let (start, end) = self.written.unwrap_or((addr, addr + 1));
+        self.written = Some((start.min(addr), end.max(addr + 1)));
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        if port == 2 {
+            self.key = val & 0xF;
+        }
+    }
+
+    fn ef(&mut self, line: u8) -> bool {
+        match line {
+            1 => {
+                self.ef1 = !self.ef1;
+                self.ef1
+            }
+            3 => self.keypad[self.key as usize],
+            _ => false,
+        }
+    }
+}
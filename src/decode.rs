@@ -0,0 +1,210 @@
+// Instruction decoding. `decode` turns the two opcode bytes into an `Op` once,
+// so the interpreter can cache it per address instead of re-extracting the
+// nibbles on every execution. X and Y are register indices.
+//
+// Quirks are not applied here: they are configuration that may change while a
+// program runs, so `CPU` consults them when an `Op` executes. The platform
+// does decide decoding, since the dialects give the same opcodes different
+// meanings; `decode` is plain CHIP-8 and `decode_for` the rest.
+
+use crate::cpu::Platform;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Clear,                // 00E0
+    Return,               // 00EE
+    MachineCall(u16),     // 0NNN, a CDP1802 subroutine
+    Jump(u16),            // 1NNN
+    Call(u16),            // 2NNN
+    SkipEqImm(u8, u8),    // 3XNN
+    SkipNeImm(u8, u8),    // 4XNN
+    SkipEq(u8, u8),       // 5XY0
+    SetImm(u8, u8),       // 6XNN
+    AddImm(u8, u8),       // 7XNN
+    Set(u8, u8),          // 8XY0
+    Or(u8, u8),           // 8XY1
+    And(u8, u8),          // 8XY2
+    Xor(u8, u8),          // 8XY3
+    Add(u8, u8),          // 8XY4
+    Sub(u8, u8),          // 8XY5
+    ShiftRight(u8, u8),   // 8XY6
+    SubReverse(u8, u8),   // 8XY7
+    ShiftLeft(u8, u8),    // 8XYE
+    SkipNe(u8, u8),       // 9XY0
+    SetI(u16),            // ANNN
+    JumpOffset(u8, u16),  // BNNN, X only matters with the jump_vx quirk
+    Random(u8, u8),       // CXNN
+    Draw(u8, u8, u8),     // DXYN
+    SkipKey(u8),          // EX9E
+    SkipNoKey(u8),        // EXA1
+    GetDelay(u8),         // FX07
+    WaitKey(u8),          // FX0A
+    SetDelay(u8),         // FX15
+    SetSound(u8),         // FX18
+    AddI(u8),             // FX1E
+    Font(u8),             // FX29
+    BigFont(u8),          // FX30, only with a font that has big digits
+    Bcd(u8),              // FX33
+    Store(u8),            // FX55
+    Load(u8),             // FX65
+    CycleBackground,      // 02A0 (CHIP-8X)
+    AddNibbles(u8, u8),   // 5XY1 (CHIP-8X)
+    ZoneColour(u8, u8),   // BXY0 (CHIP-8X)
+    SpriteColour(u8, u8, u8), // BXYN (CHIP-8X)
+    SkipKey2(u8),         // EXF2 (CHIP-8X)
+    SkipNoKey2(u8),       // EXF5 (CHIP-8X)
+    MegaOff,              // 0010 (MegaChip)
+    MegaOn,               // 0011 (MegaChip)
+    LongIndex(u8),        // 01NN NNNN (MegaChip), the only four-byte instruction
+    LoadPalette(u8),      // 02NN (MegaChip)
+    SpriteWidth(u8),      // 03NN (MegaChip)
+    SpriteHeight(u8),     // 04NN (MegaChip)
+    ScreenAlpha(u8),      // 05NN (MegaChip)
+    PlaySample(u8),       // 060N (MegaChip)
+    StopSample,           // 0700 (MegaChip)
+    BlendMode(u8),        // 080N (MegaChip)
+    CollisionColour(u8),  // 09NN (MegaChip)
+    Stop,                 // 00ED (CHIP-8E)
+    NoOp,                 // 00F2 (CHIP-8E)
+    WaitDelay,            // 0151 (CHIP-8E)
+    SkipNext,             // 0188 (CHIP-8E)
+    SkipGreater(u8, u8),  // 5XY1 (CHIP-8E)
+    StoreRange(u8, u8),   // 5XY2 (CHIP-8E)
+    LoadRange(u8, u8),    // 5XY3 (CHIP-8E)
+    BranchBack(u8),       // BBNN (CHIP-8E)
+    BranchForward(u8),    // BFNN (CHIP-8E)
+    Output(u8),           // FX03 (CHIP-8E)
+    SkipBytes(u8),        // FX1B (CHIP-8E)
+    SetDelayWait(u8),     // FX4F (CHIP-8E)
+    InputWait(u8),        // FXE3 (CHIP-8E)
+    Input(u8),            // FXE7 (CHIP-8E)
+    Invalid(u16),         // anything else; fails when executed
+}
+
+pub fn decode(instr: u16) -> Op {
+    let x = ((instr & 0x0F00) >> 8) as u8;
+    let y = ((instr & 0x00F0) >> 4) as u8;
+    let n = (instr & 0x000F) as u8;
+    let nn = (instr & 0x00FF) as u8;
+    let nnn = instr & 0x0FFF;
+    match instr >> 12 {
+        0x0 => match nn {
+            0xE0 => Op::Clear,
+            0xEE => Op::Return,
+            _ => Op::MachineCall(nnn),
+        },
+        0x1 => Op::Jump(nnn),
+        0x2 => Op::Call(nnn),
+        0x3 => Op::SkipEqImm(x, nn),
+        0x4 => Op::SkipNeImm(x, nn),
+        0x5 => Op::SkipEq(x, y),
+        0x6 => Op::SetImm(x, nn),
+        0x7 => Op::AddImm(x, nn),
+        0x8 => match n {
+            0x0 => Op::Set(x, y),
+            0x1 => Op::Or(x, y),
+            0x2 => Op::And(x, y),
+            0x3 => Op::Xor(x, y),
+            0x4 => Op::Add(x, y),
+            0x5 => Op::Sub(x, y),
+            0x6 => Op::ShiftRight(x, y),
+            0x7 => Op::SubReverse(x, y),
+            0xE => Op::ShiftLeft(x, y),
+            _ => Op::Invalid(instr),
+        },
+        0x9 => Op::SkipNe(x, y),
+        0xA => Op::SetI(nnn),
+        0xB => Op::JumpOffset(x, nnn),
+        0xC => Op::Random(x, nn),
+        0xD => Op::Draw(x, y, n),
+        0xE => match nn {
+            0x9E => Op::SkipKey(x),
+            0xA1 => Op::SkipNoKey(x),
+            _ => Op::Invalid(instr),
+        },
+        _ => match nn {
+            0x07 => Op::GetDelay(x),
+            0x0A => Op::WaitKey(x),
+            0x15 => Op::SetDelay(x),
+            0x18 => Op::SetSound(x),
+            0x1E => Op::AddI(x),
+            0x29 => Op::Font(x),
+            0x30 => Op::BigFont(x),
+            0x33 => Op::Bcd(x),
+            0x55 => Op::Store(x),
+            0x65 => Op::Load(x),
+            _ => Op::Invalid(instr),
+        },
+    }
+}
+
+pub fn decode_for(platform: Platform, instr: u16) -> Op {
+    match platform {
+        Platform::Chip8 => decode(instr),
+        Platform::Chip8X => decode_chip8x(instr),
+        Platform::MegaChip => decode_megachip(instr),
+        Platform::Chip8E => decode_chip8e(instr),
+    }
+}
+
+// CHIP-8X takes over 02A0, 5XY1 and the whole of BNNN, and adds EXF2/EXF5
+fn decode_chip8x(instr: u16) -> Op {
+    let x = ((instr & 0x0F00) >> 8) as u8;
+    let y = ((instr & 0x00F0) >> 4) as u8;
+    let n = (instr & 0x000F) as u8;
+    match (instr >> 12, instr & 0xFF) {
+        (0x0, _) if instr == 0x02A0 => Op::CycleBackground,
+        (0x5, _) if n == 1 => Op::AddNibbles(x, y),
+        (0xB, _) if n == 0 => Op::ZoneColour(x, y),
+        (0xB, _) => Op::SpriteColour(x, y, n),
+        (0xE, 0xF2) => Op::SkipKey2(x),
+        (0xE, 0xF5) => Op::SkipNoKey2(x),
+        _ => decode(instr),
+    }
+}
+
+// MegaChip takes over 0010-09FF, which would otherwise be machine code calls
+fn decode_megachip(instr: u16) -> Op {
+    let nn = (instr & 0x00FF) as u8;
+    match instr >> 8 {
+        0x00 if nn == 0x10 => Op::MegaOff,
+        0x00 if nn == 0x11 => Op::MegaOn,
+        0x01 => Op::LongIndex(nn),
+        0x02 => Op::LoadPalette(nn),
+        0x03 => Op::SpriteWidth(nn),
+        0x04 => Op::SpriteHeight(nn),
+        0x05 => Op::ScreenAlpha(nn),
+        0x06 if nn < 0x10 => Op::PlaySample(nn),
+        0x07 if nn == 0 => Op::StopSample,
+        0x08 if nn < 0x10 => Op::BlendMode(nn),
+        0x09 => Op::CollisionColour(nn),
+        _ => decode(instr),
+    }
+}
+
+// CHIP-8E fills in 5XY1-5XY3, BBNN/BFNN and a few 0NNN and FXNN gaps
+fn decode_chip8e(instr: u16) -> Op {
+    let x = ((instr & 0x0F00) >> 8) as u8;
+    let y = ((instr & 0x00F0) >> 4) as u8;
+    let nn = (instr & 0x00FF) as u8;
+    match (instr >> 12, instr & 0xF) {
+        _ if instr == 0x00ED => Op::Stop,
+        _ if instr == 0x00F2 => Op::NoOp,
+        _ if instr == 0x0151 => Op::WaitDelay,
+        _ if instr == 0x0188 => Op::SkipNext,
+        (0x5, 1) => Op::SkipGreater(x, y),
+        (0x5, 2) => Op::StoreRange(x, y),
+        (0x5, 3) => Op::LoadRange(x, y),
+        (0xB, _) if x == 0xB => Op::BranchBack(nn),
+        (0xB, _) if x == 0xF => Op::BranchForward(nn),
+        (0xF, _) => match nn {
+            0x03 => Op::Output(x),
+            0x1B => Op::SkipBytes(x),
+            0x4F => Op::SetDelayWait(x),
+            0xE3 => Op::InputWait(x),
+            0xE7 => Op::Input(x),
+            _ => decode(instr),
+        },
+        _ => decode(instr),
+    }
+}
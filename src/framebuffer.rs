@@ -0,0 +1,120 @@
+// Bit-packed monochrome framebuffer. Each row is one integer with column 0 in
+// the most significant bit, so drawing a sprite row is a shift and an XOR, and
+// collision detection is a single AND.
+//
+// `Framebuffer<u64>` is the 64 pixel wide CHIP-8 display; `Framebuffer<u128>`
+// has room for 128 pixel wide hires modes. Rows that change are marked dirty
+// until the frontend takes them, so presenting a frame only converts those.
+
+use std::fmt;
+use std::ops::{BitAnd, BitXor};
+
+pub trait Row: Copy + Default + PartialEq + fmt::Debug + BitAnd<Output = Self> + BitXor<Output = Self> {
+    const WIDTH: usize;
+
+    // `byte` with its top bit at column `x`; bits past the right edge are
+    // dropped, or come back in at the left with `wrap`
+    fn place(byte: u8, x: usize, wrap: bool) -> Self;
+
+    fn bit(self, x: usize) -> bool;
+}
+
+macro_rules! impl_row {
+    ($t:ty) => {
+        impl Row for $t {
+            const WIDTH: usize = <$t>::BITS as usize;
+
+            fn place(byte: u8, x: usize, wrap: bool) -> Self {
+                let top = (byte as $t) << (Self::WIDTH - 8);
+                if wrap { top.rotate_right(x as u32) } else { top >> x }
+            }
+
+            fn bit(self, x: usize) -> bool {
+                (self >> (Self::WIDTH - 1 - x)) & 1 != 0
+            }
+        }
+    };
+}
+
+impl_row!(u64);
+impl_row!(u128);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer<R: Row = u64> {
+    rows: Vec<R>,
+    dirty: u64, // bit y set = row y changed since `take_dirty`
+}
+
+impl<R: Row> Framebuffer<R> {
+    pub fn new(height: usize) -> Self {
+        assert!(height <= 64, "at most 64 rows fit the dirty mask");
+        Framebuffer { rows: vec![R::default(); height], dirty: Self::all_rows(height) }
+    }
+
+    fn all_rows(height: usize) -> u64 {
+        if height == 64 { u64::MAX } else { (1 << height) - 1 }
+    }
+
+    pub fn width(&self) -> usize {
+        R::WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn rows(&self) -> &[R] {
+        &self.rows
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.rows[y].bit(x)
+    }
+
+    // every pixel, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows.iter().flat_map(|&row| (0..R::WIDTH).map(move |x| row.bit(x)))
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) {
+        if self.pixel(x, y) != lit {
+            self.rows[y] = self.rows[y] ^ R::place(0x80, x, false);
+            self.dirty |= 1 << y;
+        }
+    }
+
+    pub fn set_row(&mut self, y: usize, row: R) {
+        if self.rows[y] != row {
+            self.rows[y] = row;
+            self.dirty |= 1 << y;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.fill(R::default());
+        self.dirty = Self::all_rows(self.rows.len());
+    }
+
+    // XORs `byte` into row `y` from column `x`; true if that turned a lit pixel off
+    pub fn draw_byte(&mut self, x: usize, y: usize, byte: u8, wrap: bool) -> bool {
+        let sprite = R::place(byte, x, wrap);
+        if sprite == R::default() {
+            return false;
+        }
+        let row = &mut self.rows[y];
+        let collision = *row & sprite != R::default();
+        *row = *row ^ sprite;
+        self.dirty |= 1 << y;
+        collision
+    }
+
+    // for when the way pixels are shown changes rather than the pixels
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = Self::all_rows(self.rows.len());
+    }
+
+    // the rows changed since the last call, as a bit mask
+    pub fn take_dirty(&mut self) -> u64 {
+        std::mem::take(&mut self.dirty)
+    }
+}
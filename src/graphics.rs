@@ -0,0 +1,144 @@
+// Bitmap terminal output: encodes a frame as a Sixel or kitty graphics
+// protocol image so the display is pixel-exact rather than character-cell.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphicsProtocol {
+    Sixel,
+    Kitty,
+}
+
+impl GraphicsProtocol {
+    pub fn encode(&self, buffer: &[u32], width: usize, height: usize, scale: usize) -> String {
+        let (scaled, w, h) = upscale(buffer, width, height, scale);
+        match self {
+            GraphicsProtocol::Sixel => encode_sixel(&scaled, w, h),
+            GraphicsProtocol::Kitty => encode_kitty(&scaled, w, h),
+        }
+    }
+}
+
+fn upscale(buffer: &[u32], width: usize, height: usize, scale: usize) -> (Vec<u32>, usize, usize) {
+    let scale = scale.max(1);
+    let (w, h) = (width * scale, height * scale);
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let row = &buffer[(y / scale) * width..(y / scale + 1) * width];
+        for x in 0..w {
+            out.push(row[x / scale]);
+        }
+    }
+    (out, w, h)
+}
+
+// Sixel image with one palette register per distinct colour. Colours beyond the
+// 256 registers most terminals offer are folded onto 8-bit grey.
+pub fn encode_sixel(buffer: &[u32], width: usize, height: usize) -> String {
+    let mut palette: Vec<u32> = Vec::new();
+    for &px in buffer {
+        if !palette.contains(&px) {
+            palette.push(px);
+        }
+    }
+    let quantize = palette.len() > 256;
+    let buffer: Vec<u32> = if quantize {
+        buffer.iter().map(|&px| grey_of(px)).collect()
+    } else {
+        buffer.to_vec()
+    };
+    if quantize {
+        palette = (0..=255u32).map(|v| (v << 16) | (v << 8) | v).collect();
+    }
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for (n, &rgb) in palette.iter().enumerate() {
+        let pct = |c: u32| c * 100 / 255;
+        out.push_str(&format!("#{};2;{};{};{}", n, pct(rgb >> 16), pct((rgb >> 8) & 0xFF), pct(rgb & 0xFF)));
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut first = true;
+        for (n, &rgb) in palette.iter().enumerate() {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    (0..rows).fold(0u8, |bits, r| {
+                        bits | (((buffer[(band + r) * width + x] == rgb) as u8) << r)
+                    })
+                })
+                .collect();
+            if sixels.iter().all(|&b| b == 0) {
+                continue;
+            }
+            if !first {
+                out.push('$'); // back to the start of the band for the next colour
+            }
+            first = false;
+            out.push_str(&format!("#{}", n));
+            push_sixel_runs(&mut out, &sixels);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_sixel_runs(out: &mut String, sixels: &[u8]) {
+    let mut i = 0;
+    while i < sixels.len() {
+        let run = sixels[i..].iter().take_while(|&&b| b == sixels[i]).count();
+        let c = (b'?' + sixels[i]) as char;
+        if run > 3 {
+            out.push_str(&format!("!{}{}", run, c));
+        } else {
+            (0..run).for_each(|_| out.push(c));
+        }
+        i += run;
+    }
+}
+
+fn grey_of(rgb: u32) -> u32 {
+    let v = ((rgb >> 16) + ((rgb >> 8) & 0xFF) + (rgb & 0xFF)) / 3;
+    (v << 16) | (v << 8) | v
+}
+
+// kitty graphics protocol: raw 24-bit RGB, base64 encoded, sent in 4096 byte
+// chunks. Image id 1 is reused so each frame replaces the previous one, the
+// cursor is not moved (C=1) and terminal replies are suppressed (q=2).
+pub fn encode_kitty(buffer: &[u32], width: usize, height: usize) -> String {
+    let rgb: Vec<u8> = buffer
+        .iter()
+        .flat_map(|&px| [(px >> 16) as u8, (px >> 8) as u8, px as u8])
+        .collect();
+    let payload = base64(&rgb);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(4096).collect();
+
+    let mut out = String::with_capacity(payload.len() + chunks.len() * 16 + 64);
+    for (n, chunk) in chunks.iter().enumerate() {
+        let more = (n + 1 < chunks.len()) as u8;
+        if n == 0 {
+            out.push_str(&format!("\x1b_Ga=T,f=24,s={},v={},i=1,p=1,C=1,q=2,m={};", width, height, more));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,146 @@
+// Display-less runner: executes a ROM for a fixed number of frames with
+// scripted key input so the result can be compared against a golden image.
+
+use crate::cpu::CPU;
+use crate::framebuffer::Framebuffer;
+use crate::timing::Timing;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub down: bool,
+}
+
+// Scripted keypad input. Text form is one event per line:
+//
+//     # frame  action  key
+//     30       down    1
+//     35       up      1
+//
+// Events take effect at the start of their frame, before any instruction runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    events: Vec<KeyEvent>,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut script = InputScript::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [frame, action, key] = fields[..] else {
+                return Err(format!("Script line {}: expected `frame down|up key`", n + 1));
+            };
+            let frame = frame.parse::<u32>().map_err(|_| format!("Script line {}: bad frame {}", n + 1, frame))?;
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&k| k < 16)
+                .ok_or(format!("Script line {}: bad key {}", n + 1, key))?;
+            match action {
+                "down" => script = script.press(frame, key),
+                "up" => script = script.release(frame, key),
+                _ => return Err(format!("Script line {}: bad action {}", n + 1, action)),
+            }
+        }
+        Ok(script)
+    }
+
+    pub fn press(mut self, frame: u32, key: u8) -> Self {
+        self.events.push(KeyEvent { frame, key, down: true });
+        self
+    }
+
+    pub fn release(mut self, frame: u32, key: u8) -> Self {
+        self.events.push(KeyEvent { frame, key, down: false });
+        self
+    }
+
+    // presses `key` at `frame` and releases it `hold` frames later
+    pub fn tap(self, frame: u32, key: u8, hold: u32) -> Self {
+        self.press(frame, key).release(frame + hold, key)
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    pub frames: u32,
+    pub timing: Timing,
+    pub script: InputScript,
+    pub pokes: Vec<(usize, u8)>, // memory writes applied after loading, e.g. 0x1FF test selectors
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig {
+            frames: 60,
+            timing: Timing::default(), // 10 instructions per frame, as in the interactive frontends
+            script: InputScript::new(),
+            pokes: Vec::new(),
+        }
+    }
+}
+
+pub fn run(rom: &[u8], config: &RunConfig) -> Result<CPU, String> {
+    let mut cpu = CPU::new();
+    run_on(&mut cpu, rom, config)?;
+    Ok(cpu)
+}
+
+// Like `run`, but on a caller-prepared CPU (quirks, tracer) that survives errors.
+// Frames spent in idle loops skip straight to the end, so waiting out a timer
+// costs a few operations per frame.
+pub fn run_on(cpu: &mut CPU, rom: &[u8], config: &RunConfig) -> Result<(), String> {
+    cpu.load_bytes(rom)?;
+    for &(addr, val) in &config.pokes {
+        cpu.write_mem(addr, val);
+    }
+
+    let mut timing = config.timing.clone();
+    let mut keypad = [false; 16];
+    for frame in 0..config.frames {
+        let events = config.script.events().iter().filter(|e| e.frame == frame);
+        let mut changed = false;
+        for e in events {
+            keypad[e.key as usize] = e.down;
+            changed = true;
+        }
+        if changed {
+            cpu.set_keypad(keypad);
+        }
+        timing.run_frame(cpu)?;
+    }
+    Ok(())
+}
+
+// 64-bit FNV-1a over the lit/unlit state of every pixel, row by row
+pub fn framebuffer_hash(display: &Framebuffer) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for px in display.pixels() {
+        hash ^= px as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// one line per row, `#` for lit pixels and `.` for dark ones
+pub fn framebuffer_text(display: &Framebuffer) -> String {
+    let mut out = String::with_capacity((display.width() + 1) * display.height());
+    for y in 0..display.height() {
+        out.extend((0..display.width()).map(|x| if display.pixel(x, y) { '#' } else { '.' }));
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,228 @@
+// Closure-threaded compilation of CHIP-8 basic blocks.
+//
+// A block is a straight run of instructions from some start address up to and
+// including the first one that can change control flow (jumps, calls, skips,
+// waits like FX0A, MegaChip's 01NN), write memory (FX33, FX55, 0NNN, and
+// 00E0/DXYN with the display in memory) or fail (FX30 without big digits,
+// MegaChip instructions elsewhere). DXYN, FX65 and the like also fail when I
+// runs past the end of memory, but are too common to end blocks; a failing
+// instruction anywhere in a block leaves PC just past it. Each instruction becomes a closure with
+// its operands and quirks baked in, so running a block is a loop of indirect
+// calls with no fetch, decode or dispatch. Anything not worth specialising
+// calls back into `CPU::exec_op`, so the semantics stay the interpreter's.
+//
+// Blocks are dropped when a write touches any byte they were compiled from.
+// Because writing instructions end their block, the CPU can note its writes
+// while a block runs and hand them over before the next one starts.
+
+use std::fmt;
+use crate::cpu::{Platform, Quirks, CPU};
+use crate::decode::{decode_for, Op};
+
+const MAX_BLOCK_LEN: usize = 64;
+
+type Thunk = Box<dyn Fn(&mut CPU) -> Result<(), String> + Send + Sync>;
+
+pub(crate) struct Block {
+    start: usize,
+    ops: Vec<Thunk>,
+}
+
+impl Block {
+    fn end(&self) -> usize {
+        self.start + self.ops.len() * 2
+    }
+
+    // Runs up to `budget` instructions of the block, leaving PC and the cycle
+    // count exactly where `execute` would. Returns how many ran. Only the last
+    // instruction of a block reads or changes PC (see `ends_block`), so PC is
+    // set once up front to where `execute` would have it for that instruction,
+    // and moved back if an earlier one fails.
+    pub(crate) fn run(&self, cpu: &mut CPU, budget: usize) -> Result<usize, String> {
+        let n = self.ops.len().min(budget);
+        cpu.PC = self.start + n * 2;
+        for (k, op) in self.ops[..n].iter().enumerate() {
+            if let Err(e) = op(cpu) {
+                cpu.PC = self.start + (k + 1) * 2;
+                cpu.cycle += k as u64;
+                return Err(e);
+            }
+        }
+        cpu.cycle += n as u64;
+        Ok(n)
+    }
+}
+
+// Whether `op` has to be the last instruction of its block. 00E0 and DXYN also
+// do when they write the display into memory, which only the JIT cares about.
+pub(crate) fn ends_block(op: Op) -> bool {
+    matches!(
+        op,
+        Op::Return
+            | Op::MachineCall(_)
+            | Op::Jump(_)
+            | Op::Call(_)
+            | Op::SkipEqImm(..)
+            | Op::SkipNeImm(..)
+            | Op::SkipEq(..)
+            | Op::SkipNe(..)
+            | Op::JumpOffset(..)
+            | Op::SkipKey(_)
+            | Op::SkipNoKey(_)
+            | Op::SkipKey2(_)
+            | Op::SkipNoKey2(_)
+            | Op::LongIndex(_)
+            | Op::Stop
+            | Op::WaitDelay
+            | Op::SkipNext
+            | Op::SkipGreater(..)
+            | Op::StoreRange(..)
+            | Op::BranchBack(_)
+            | Op::BranchForward(_)
+            | Op::SkipBytes(_)
+            | Op::SetDelayWait(_)
+            | Op::InputWait(_)
+            | Op::WaitKey(_)
+            | Op::BigFont(_)
+            | Op::MegaOff
+            | Op::MegaOn
+            | Op::LoadPalette(_)
+            | Op::SpriteWidth(_)
+            | Op::SpriteHeight(_)
+            | Op::ScreenAlpha(_)
+            | Op::PlaySample(_)
+            | Op::StopSample
+            | Op::BlendMode(_)
+            | Op::CollisionColour(_)
+            | Op::Bcd(_)
+            | Op::Store(_)
+            | Op::Invalid(_)
+    )
+}
+
+fn skip_if(taken: bool, cpu: &mut CPU) -> Result<(), String> {
+    if taken {
+        cpu.PC += 2;
+    }
+    Ok(())
+}
+
+// the closure for one decoded instruction, specialised for the common cases
+fn thunk(op: Op, quirks: Quirks) -> Thunk {
+    match op {
+        Op::Jump(nnn) => Box::new(move |c| {
+            c.PC = nnn as usize;
+            Ok(())
+        }),
+        Op::SkipEqImm(x, nn) => Box::new(move |c| skip_if(c.register[x as usize] == nn, c)),
+        Op::SkipNeImm(x, nn) => Box::new(move |c| skip_if(c.register[x as usize] != nn, c)),
+        Op::SkipEq(x, y) => Box::new(move |c| skip_if(c.register[x as usize] == c.register[y as usize], c)),
+        Op::SkipNe(x, y) => Box::new(move |c| skip_if(c.register[x as usize] != c.register[y as usize], c)),
+        Op::SetImm(x, nn) => Box::new(move |c| {
+            c.register[x as usize] = nn;
+            Ok(())
+        }),
+        Op::AddImm(x, nn) => Box::new(move |c| {
+            c.register[x as usize] = c.register[x as usize].wrapping_add(nn);
+            Ok(())
+        }),
+        Op::Set(x, y) => Box::new(move |c| {
+            c.register[x as usize] = c.register[y as usize];
+            Ok(())
+        }),
+        Op::Or(..) | Op::And(..) | Op::Xor(..) if quirks.vf_reset => Box::new(move |c| c.exec_op(op)),
+        Op::Or(x, y) => Box::new(move |c| {
+            c.register[x as usize] |= c.register[y as usize];
+            Ok(())
+        }),
+        Op::And(x, y) => Box::new(move |c| {
+            c.register[x as usize] &= c.register[y as usize];
+            Ok(())
+        }),
+        Op::Xor(x, y) => Box::new(move |c| {
+            c.register[x as usize] ^= c.register[y as usize];
+            Ok(())
+        }),
+        Op::Add(x, y) => Box::new(move |c| {
+            let (sum, carry) = c.register[x as usize].overflowing_add(c.register[y as usize]);
+            c.register[x as usize] = sum;
+            c.register[0xF] = carry as u8;
+            Ok(())
+        }),
+        Op::SetI(nnn) => Box::new(move |c| {
+            c.I = nnn as u32;
+            Ok(())
+        }),
+        _ => Box::new(move |c| c.exec_op(op)),
+    }
+}
+
+// compiles the block starting at `start`; empty if no whole instruction fits there
+fn compile(mem: &[u8], quirks: Quirks, platform: Platform, memory_display: bool, start: usize) -> Block {
+    let mut ops = Vec::new();
+    let mut addr = start;
+    while ops.len() < MAX_BLOCK_LEN && addr + 1 < mem.len() {
+        let op = decode_for(platform, (mem[addr] as u16) << 8 | mem[addr + 1] as u16);
+        ops.push(thunk(op, quirks));
+        addr += 2;
+        if ends_block(op) || memory_display && matches!(op, Op::Clear | Op::Draw(..)) {
+            break;
+        }
+    }
+    Block { start, ops }
+}
+
+#[derive(Default)]
+pub(crate) struct BlockCache {
+    blocks: Vec<Option<Block>>, // by start address
+    starts: Vec<usize>,              // addresses that have a block, for invalidation
+    covered: Vec<u8>,                // per byte, how many blocks were compiled from it
+}
+
+impl fmt::Debug for BlockCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BlockCache({} blocks)", self.starts.len())
+    }
+}
+
+impl BlockCache {
+    // the block at `pc`, compiling it from `mem` on first use
+    pub(crate) fn get(&mut self, mem: &[u8], quirks: Quirks, platform: Platform, memory_display: bool, pc: usize) -> &Block {
+        if self.blocks.is_empty() {
+            self.blocks.resize_with(mem.len(), || None);
+            self.covered = vec![0; mem.len()];
+        }
+        if self.blocks[pc].is_none() {
+            let block = compile(mem, quirks, platform, memory_display, pc);
+            for n in &mut self.covered[block.start..block.end()] {
+                *n += 1;
+            }
+            self.blocks[pc] = Some(block);
+            self.starts.push(pc);
+        }
+        self.blocks[pc].as_ref().unwrap()
+    }
+
+    // drops every block compiled from any of the `len` bytes at `addr`
+    pub(crate) fn invalidate(&mut self, addr: usize, len: usize) {
+        if self.starts.is_empty() {
+            return;
+        }
+        let end = (addr + len).min(self.covered.len());
+        if self.covered[addr.min(end)..end].iter().all(|&n| n == 0) {
+            return;
+        }
+        let (blocks, covered) = (&mut self.blocks, &mut self.covered);
+        self.starts.retain(|&start| {
+            let block = blocks[start].as_ref().unwrap();
+            if block.start >= end || block.end() <= addr {
+                return true;
+            }
+            for n in &mut covered[block.start..block.end()] {
+                *n -= 1;
+            }
+            blocks[start] = None;
+            false
+        });
+    }
+}
@@ -0,0 +1,49 @@
+// Which host keys press which CHIP-8 keys in the window and terminal
+// frontends. A layout is 16 characters, the host keys for CHIP-8 keys 0 to F
+// in that order. There are two keypads: the usual one, and the CHIP-8X second
+// one read by EXF2/EXF5. The libretro core reads the second keypad from the
+// second controller, which the frontend's own input settings remap.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    pads: [[char; 16]; 2],
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::new(Self::FIRST, Self::SECOND).unwrap()
+    }
+}
+
+impl KeyMap {
+    // 1234/QWER/ASDF/ZXCV, as the hex keypad is laid out
+    pub const FIRST: &'static str = "x123qweasdzc4rfv";
+    // the same shape further right: 7890/UIOP/JKL;/M,./
+    pub const SECOND: &'static str = ",789uiojklm.0p;/";
+
+    // the keys for CHIP-8 keys 0 to F; letters are not case sensitive
+    pub fn parse(layout: &str) -> Result<[char; 16], String> {
+        let keys: Vec<char> = layout.chars().map(|c| c.to_ascii_lowercase()).collect();
+        let keys: [char; 16] = keys.try_into().map_err(|_| format!("A key layout needs 16 keys: {}", layout))?;
+        if let Some(c) = keys.iter().enumerate().find_map(|(n, c)| keys[..n].contains(c).then_some(c)) {
+            return Err(format!("Key '{}' is used twice in {}", c, layout));
+        }
+        Ok(keys)
+    }
+
+    // The layouts of the first and second keypad. A key on both is refused,
+    // since one press cannot go to both.
+    pub fn new(first: &str, second: &str) -> Result<Self, String> {
+        let pads = [Self::parse(first)?, Self::parse(second)?];
+        if let Some(c) = pads[0].iter().find(|c| pads[1].contains(c)) {
+            return Err(format!("Key '{}' is on both keypads", c));
+        }
+        Ok(KeyMap { pads })
+    }
+
+    // the keypad (0 or 1) and CHIP-8 key that host key `c` presses
+    pub fn lookup(&self, c: char) -> Option<(usize, u8)> {
+        let c = c.to_ascii_lowercase();
+        (0..2).find_map(|pad| self.pads[pad].iter().position(|&k| k == c).map(|key| (pad, key as u8)))
+    }
+}
@@ -0,0 +1,22 @@
+pub mod bench;
+pub mod cdp1802;
+pub mod chip8x;
+pub mod cpu;
+pub mod decode;
+pub mod font;
+pub mod framebuffer;
+pub mod graphics;
+pub mod headless;
+mod jit;
+pub mod keymap;
+pub mod libretro;
+pub mod megachip;
+pub mod phosphor;
+pub mod profile;
+pub mod recompile;
+pub mod terminal;
+pub mod timing;
+pub mod trace;
+pub mod trace_diff;
+pub mod vip;
+pub mod window;
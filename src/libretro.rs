@@ -0,0 +1,477 @@
+// libretro core: exposes `CPU` through the C API that multi-system frontends
+// (RetroArch and friends) load from the `cdylib` build of this crate.
+//
+// libretro is a global, single-instance API, so the running core and the
+// frontend's callbacks live in statics. The pointer arguments of the exported
+// functions follow the contracts in libretro.h.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr};
+use std::sync::Mutex;
+use crate::cpu::{CPU, Platform, Quirks, StackConfig, WIDTH, HEIGHT};
+use crate::font::Font;
+use crate::megachip;
+use crate::profile::MachineProfile;
+
+const RETRO_API_VERSION: c_uint = 1;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const RETRO_ENVIRONMENT_SET_GEOMETRY: c_uint = 37;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_int = 1;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44100.0;
+const SAMPLES_PER_FRAME: usize = 735; // SAMPLE_RATE / FPS
+const BEEP_HZ: f32 = 440.0;
+const BEEP_VOLUME: i16 = 4000;
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    pub geometry: RetroGameGeometry,
+    pub timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+#[repr(C)]
+pub struct RetroVariable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+#[repr(C)]
+pub struct RetroInputDescriptor {
+    pub port: c_uint,
+    pub device: c_uint,
+    pub index: c_uint,
+    pub id: c_uint,
+    pub description: *const c_char,
+}
+
+pub type EnvironmentFn = unsafe extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type VideoRefreshFn = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+pub type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type InputPollFn = unsafe extern "C" fn();
+pub type InputStateFn = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+// RetroPad button id (B, Y, Select, Start, Up, Down, Left, Right, A, X, L, R,
+// L2, R2, L3, R3) -> CHIP-8 key. The d-pad lands on 2/8/4/6 and B on 5, the
+// usual movement and action keys of CHIP-8 games.
+const JOYPAD_KEYS: [u8; 16] = [0x5, 0x0, 0xA, 0xB, 0x2, 0x8, 0x4, 0x6, 0x1, 0x3, 0x7, 0x9, 0xC, 0xD, 0xE, 0xF];
+
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    cpu: CPU,
+    rom: Vec<u8>,
+    ipf: u32,
+    halted: bool, // set when the program hits an instruction we cannot execute
+    phase: f32,   // beep oscillator position, 0..1
+    buffer: Vec<u32>, // XRGB8888 frame, sized for the display; only changed rows are updated
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+const VARIABLES: [(&CStr, &CStr); 13] = [
+    (c"chip8_ipf", c"Instructions per frame; 10|5|8|12|15|20|30|50|100|200|500|1000"),
+    (c"chip8_quirk_vf_reset", c"Quirk: 8XY1/8XY2/8XY3 reset VF; disabled|enabled"),
+    (c"chip8_quirk_increment_i", c"Quirk: FX55/FX65 increment I; disabled|enabled"),
+    (c"chip8_quirk_shift_vx", c"Quirk: 8XY6/8XYE shift VX only; disabled|enabled"),
+    (c"chip8_quirk_jump_vx", c"Quirk: BNNN jumps with VX; disabled|enabled"),
+    (c"chip8_quirk_wrap", c"Quirk: sprites wrap around the screen; disabled|enabled"),
+    (c"chip8_stack_depth", c"Stack depth; 16|12|24|64"),
+    (c"chip8_stack_in_memory", c"Stack in RAM at 0xEA0 (depth 24 at most); disabled|enabled"),
+    (c"chip8_memory_display", c"Display in RAM at 0xF00; disabled|enabled"),
+    (c"chip8_hybrid", c"0NNN runs CDP1802 machine code; disabled|enabled"),
+    (c"chip8_platform", c"Platform (restarts the game); chip8|chip8x|megachip|chip8e"),
+    (c"chip8_machine", c"Machine (restarts the game); chip8|vip|dream6800|eti660"),
+    (c"chip8_font", c"Font (restarts the game); machine|chip8|vip|dream6800|eti660|fish|schip|octo"),
+];
+
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match CALLBACKS.lock().unwrap().environment {
+        Some(env) => unsafe { env(cmd, data) },
+        None => false,
+    }
+}
+
+fn get_variable(key: &CStr) -> Option<String> {
+    let mut var = RetroVariable { key: key.as_ptr(), value: std::ptr::null() };
+    if !environment(RETRO_ENVIRONMENT_GET_VARIABLE, &mut var as *mut _ as *mut c_void) || var.value.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(var.value) }.to_string_lossy().into_owned())
+}
+
+fn apply_options(core: &mut Core) {
+    let enabled = |key: &CStr| get_variable(key).is_some_and(|v| v == "enabled");
+    core.ipf = get_variable(c"chip8_ipf").and_then(|v| v.parse().ok()).unwrap_or(10);
+    core.cpu.set_quirks(Quirks {
+        vf_reset: enabled(c"chip8_quirk_vf_reset"),
+        increment_i: enabled(c"chip8_quirk_increment_i"),
+        shift_vx: enabled(c"chip8_quirk_shift_vx"),
+        jump_vx: enabled(c"chip8_quirk_jump_vx"),
+        wrap_sprites: enabled(c"chip8_quirk_wrap"),
+    });
+    let in_memory = enabled(c"chip8_stack_in_memory");
+    let depth: usize = get_variable(c"chip8_stack_depth").and_then(|v| v.parse().ok()).unwrap_or(16);
+    let depth = if in_memory { depth.min(24) } else { depth };
+    // refused while the running program is nested deeper than the new depth
+    let _ = core.cpu.set_stack_config(StackConfig { depth, in_memory });
+    core.cpu.set_memory_display(enabled(c"chip8_memory_display"));
+    core.cpu.set_hybrid(enabled(c"chip8_hybrid"));
+    let platform = get_variable(c"chip8_platform").and_then(|v| Platform::parse(&v).ok()).unwrap_or_default();
+    let mut profile = get_variable(c"chip8_machine").and_then(|v| MachineProfile::parse(&v).ok()).unwrap_or_default();
+    if let Some(font) = get_variable(c"chip8_font").and_then(|v| Font::named(&v).ok()) {
+        profile.font = font; // anything else, such as "machine", keeps the machine's own
+    }
+    if platform != core.cpu.platform() || profile != *core.cpu.profile() {
+        // the ROM loads somewhere else, so it has to start over; if it does
+        // not fit there, the game carries on as it was
+        if let Err(e) = restart(core, platform, profile) {
+            eprintln!("chip8: {}", e);
+        }
+    }
+}
+
+// the display as the frontend should show it, with square pixels
+fn geometry(cpu: Option<&CPU>) -> RetroGameGeometry {
+    let (width, height) = cpu.map_or((WIDTH, HEIGHT), |cpu| cpu.output_size());
+    RetroGameGeometry {
+        base_width: width as c_uint,
+        base_height: height as c_uint,
+        max_width: megachip::WIDTH as c_uint,
+        max_height: megachip::HEIGHT as c_uint,
+        aspect_ratio: width as f32 / height as f32,
+    }
+}
+
+// a fresh machine with the same settings and the ROM loaded again
+fn reset(core: &mut Core) -> Result<(), String> {
+    restart(core, core.cpu.platform(), core.cpu.profile().clone())
+}
+
+// The same, on `platform` and `profile`. Nothing changes unless the ROM
+// loads, so an error leaves the old machine running.
+fn restart(core: &mut Core, platform: Platform, profile: MachineProfile) -> Result<(), String> {
+    let mut cpu = CPU::new();
+    cpu.set_profile(profile)?;
+    cpu.set_platform(platform);
+    cpu.set_quirks(core.cpu.quirks());
+    cpu.set_stack_config(core.cpu.stack_config())?;
+    cpu.set_memory_display(core.cpu.memory_display());
+    cpu.set_hybrid(core.cpu.hybrid());
+    cpu.load_bytes(&core.rom)?;
+    core.cpu = cpu;
+    core.halted = false;
+    let (width, height) = core.cpu.output_size();
+    if core.buffer.len() != width * height {
+        // a HIRES ROM is only one on some platforms, and MegaChip is bigger
+        core.buffer = vec![0; width * height];
+        let mut geometry = geometry(Some(&core.cpu));
+        environment(RETRO_ENVIRONMENT_SET_GEOMETRY, &mut geometry as *mut _ as *mut c_void);
+    }
+    Ok(())
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(cb: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(cb);
+
+    let mut variables: Vec<RetroVariable> = VARIABLES
+        .iter()
+        .map(|(key, value)| RetroVariable { key: key.as_ptr(), value: value.as_ptr() })
+        .collect();
+    variables.push(RetroVariable { key: std::ptr::null(), value: std::ptr::null() });
+    environment(RETRO_ENVIRONMENT_SET_VARIABLES, variables.as_mut_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(cb: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_cb: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(cb: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_batch = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(cb: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(cb: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: c"CHIP-8".as_ptr(),
+        library_version: c"0.1.0".as_ptr(),
+        valid_extensions: c"ch8|c8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: geometry(CORE.lock().unwrap().as_ref().map(|core| &core.cpu)),
+        timing: RetroSystemTiming { fps: FPS, sample_rate: SAMPLE_RATE },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let rom = std::slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut _ as *mut c_void) {
+        return false;
+    }
+
+    let names: Vec<std::ffi::CString> = JOYPAD_KEYS
+        .iter()
+        .map(|k| std::ffi::CString::new(format!("Key {:X}", k)).unwrap())
+        .collect();
+    let mut descriptors: Vec<RetroInputDescriptor> = names
+        .iter()
+        .enumerate()
+        .map(|(id, name)| RetroInputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id: id as c_uint,
+            description: name.as_ptr(),
+        })
+        .collect();
+    descriptors.push(RetroInputDescriptor { port: 0, device: 0, index: 0, id: 0, description: std::ptr::null() });
+    environment(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors.as_mut_ptr() as *mut c_void);
+
+    let mut cpu = CPU::new();
+    if let Err(e) = cpu.load_bytes(&rom) {
+        eprintln!("chip8: {}", e);
+        return false;
+    }
+    let (width, height) = cpu.output_size();
+    let buffer = vec![0; width * height];
+    let mut core = Core { cpu, rom, ipf: 10, halted: false, phase: 0.0, buffer };
+    apply_options(&mut core);
+    *CORE.lock().unwrap() = Some(core);
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_type: c_uint, _info: *const RetroGameInfo, _num: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        if let Err(e) = reset(core) {
+            eprintln!("chip8: {}", e);
+            core.halted = true;
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let mut guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_mut() else {
+        return;
+    };
+
+    let mut updated = false;
+    if environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut _ as *mut c_void) && updated {
+        apply_options(core);
+    }
+
+    let (video_refresh, audio_batch, input_poll, input_state) = {
+        let cb = CALLBACKS.lock().unwrap();
+        (cb.video_refresh, cb.audio_batch, cb.input_poll, cb.input_state)
+    };
+
+    if let (Some(poll), Some(state)) = (input_poll, input_state) {
+        // the second controller is the CHIP-8X second keypad
+        let mut keypads = [[false; 16]; 2];
+        unsafe {
+            poll();
+            for (port, keypad) in keypads.iter_mut().enumerate() {
+                for (id, &key) in JOYPAD_KEYS.iter().enumerate() {
+                    keypad[key as usize] |= state(port as c_uint, RETRO_DEVICE_JOYPAD, 0, id as c_uint) != 0;
+                }
+            }
+        }
+        core.cpu.set_keypad(keypads[0]);
+        core.cpu.set_keypad2(keypads[1]);
+    }
+
+    if !core.halted {
+        if let Err(e) = core.cpu.run_frame(core.ipf) {
+            eprintln!("chip8: {}", e);
+            core.halted = true;
+        }
+    }
+
+    core.cpu.update_display_buffer(&mut core.buffer);
+    if let Some(video) = video_refresh {
+        let (width, height) = core.cpu.output_size();
+        unsafe { video(core.buffer.as_ptr() as *const c_void, width as c_uint, height as c_uint, width * 4) };
+    }
+
+    if let Some(audio) = audio_batch {
+        let mut samples = [0i16; SAMPLES_PER_FRAME * 2];
+        if let Some(mega) = core.cpu.megachip_mut().filter(|mega| mega.playing()) {
+            // a MegaChip sample takes over from the beep
+            let mut mono = [0i16; SAMPLES_PER_FRAME];
+            mega.mix(&mut mono, SAMPLE_RATE as u32);
+            for (frame, &level) in samples.chunks_mut(2).zip(&mono) {
+                frame.fill(level);
+            }
+        } else if core.cpu.sound_timer() > 0 {
+            for frame in samples.chunks_mut(2) {
+                let level = if core.phase < 0.5 { BEEP_VOLUME } else { -BEEP_VOLUME };
+                frame.fill(level);
+                core.phase = (core.phase + BEEP_HZ / SAMPLE_RATE as f32).fract();
+            }
+        }
+        unsafe { audio(samples.as_ptr(), SAMPLES_PER_FRAME) };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    CPU::state_size()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_ref() else {
+        return false;
+    };
+    match core.cpu.save_state() {
+        Ok(state) if state.len() <= size => {
+            std::ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_mut() else {
+        return false;
+    };
+    let state = std::slice::from_raw_parts(data as *const u8, size);
+    let ok = core.cpu.load_state(state).is_ok();
+    if ok {
+        core.halted = false;
+    }
+    ok
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
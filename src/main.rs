@@ -1,15 +1,336 @@
-use crate::cpu::CPU;
-use std::time::{Duration, Instant};
-use minifb::{Window, WindowOptions};
-pub mod cpu;
+use chip8_emulator::bench::{self, BenchConfig, Limit};
+use chip8_emulator::cpu::{Platform, StackConfig, CPU};
+use chip8_emulator::font::Font;
+use chip8_emulator::graphics::GraphicsProtocol;
+use chip8_emulator::headless::{self, InputScript, RunConfig};
+use chip8_emulator::keymap::KeyMap;
+use chip8_emulator::phosphor::DisplayMode;
+use chip8_emulator::profile::MachineProfile;
+use chip8_emulator::recompile;
+use chip8_emulator::terminal::{self, Video};
+use chip8_emulator::timing::Timing;
+use chip8_emulator::trace::{Level, TraceConfig, Tracer};
+use chip8_emulator::trace_diff;
+use chip8_emulator::vip::{self, Vip};
+use chip8_emulator::window;
+
+// how to run the emulator, as given on the command line
+struct Options {
+    rom: String,
+    display_mode: Option<DisplayMode>, // the ROM's own mode, if it has one, when not given
+    headless_frames: Option<u32>,
+    script: InputScript,
+    frontend: String,
+    scale: usize,
+    keys: KeyMap,
+    timing: Timing,
+}
 
 fn main() -> Result<(), String>{
+    let mut opts = Options {
+        rom: "test_roms/6-keypad.ch8".to_string(),
+        display_mode: None,
+        headless_frames: None,
+        script: InputScript::new(),
+        frontend: "window".to_string(),
+        scale: 8,
+        keys: KeyMap::default(),
+        timing: Timing::default(),
+    };
+    let mut trace_config = TraceConfig::default();
+    let mut trace_file = None;
+    let mut trace_ring = None;
+    let mut ipf = None;
+    let mut jit = false;
+    let mut stack = StackConfig::default();
+    let mut stack_in_memory = false;
+    let mut memory_display = false;
+    let mut hybrid = false;
+    let mut platform = Platform::default();
+    let mut profile = MachineProfile::default();
+    let mut timing = None;
+    let mut font = None;
+    let mut layouts = [None, None]; // checked against each other once both are known
+
+    // usage: chip8-emulator [ROM] [--display direct|phosphor[:DECAY]|blend[:FRAMES]]
+    //                             [--frontend window|terminal|sixel|kitty] [--scale N] [--keys LAYOUT] [--keys2 LAYOUT]
+    //                             [--timing fixed|vip] [--ipf N] [--jit]
+    //                             [--stack vip|schip|xochip|DEPTH] [--stack-in-memory] [--memory-display]
+    //                             [--hybrid] [--platform chip8|chip8x|megachip|chip8e]
+    //                             [--machine chip8|vip|dream6800|eti660] [--font NAME|FILE]
+    //                             [--headless FRAMES] [--script FILE]
+    //                             [--trace FILE | --trace-ring N] [--trace-level off|info|trace]
+    //                             [--trace-categories cpu,draw,input,timers] [--trace-pc LO-HI] [--trace-ops D,F]
+    //        chip8-emulator diff OURS REFERENCE [--context N]
+    //        chip8-emulator recompile ROM [-o FILE.rs]
+    //        chip8-emulator bench ROM [--instructions N | --seconds S] [--ipf N] [--jit]
+    //        chip8-emulator vip ROM --interpreter FILE [--monitor FILE] [--ram BYTES] [--frames N | --check N]
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if_eq("diff").is_some() {
+        return diff(args);
+    }
+    if args.next_if_eq("recompile").is_some() {
+        return recompile(args);
+    }
+    if args.next_if_eq("bench").is_some() {
+        return bench(args);
+    }
+    if args.next_if_eq("vip").is_some() {
+        return run_vip(args);
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--display" => {
+                let value = args.next().ok_or("--display needs a mode")?;
+                opts.display_mode = Some(DisplayMode::parse(&value)?);
+            }
+            "--frontend" => {
+                opts.frontend = args.next().ok_or("--frontend needs a name")?;
+            }
+            "--scale" => {
+                let value = args.next().ok_or("--scale needs a factor")?;
+                opts.scale = value.parse::<usize>().map_err(|_| format!("Invalid scale: {}", value))?;
+            }
+            "--keys" => layouts[0] = Some(args.next().ok_or("--keys needs a layout")?),
+            "--keys2" => layouts[1] = Some(args.next().ok_or("--keys2 needs a layout")?),
+            "--timing" => timing = Some(Timing::parse(&args.next().ok_or("--timing needs a mode")?)?),
+            "--ipf" => {
+                let value = args.next().ok_or("--ipf needs an instruction count")?;
+                ipf = Some(value.parse::<u32>().map_err(|_| format!("Invalid instruction count: {}", value))?);
+            }
+            "--jit" => jit = true,
+            "--stack" => stack = StackConfig::parse(&args.next().ok_or("--stack needs a depth")?)?,
+            "--stack-in-memory" => stack_in_memory = true,
+            "--memory-display" => memory_display = true,
+            "--hybrid" => hybrid = true,
+            "--platform" => platform = Platform::parse(&args.next().ok_or("--platform needs a name")?)?,
+            "--machine" => profile = MachineProfile::parse(&args.next().ok_or("--machine needs a name")?)?,
+            "--font" => font = Some(args.next().ok_or("--font needs a name or a file")?),
+            "--headless" => {
+                let value = args.next().ok_or("--headless needs a frame count")?;
+                opts.headless_frames = Some(value.parse::<u32>().map_err(|_| format!("Invalid frame count: {}", value))?);
+            }
+            "--script" => {
+                let path = args.next().ok_or("--script needs a file")?;
+                let text = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+                opts.script = InputScript::parse(&text)?;
+            }
+            "--trace" => trace_file = Some(args.next().ok_or("--trace needs a file")?),
+            "--trace-ring" => {
+                let value = args.next().ok_or("--trace-ring needs an entry count")?;
+                trace_ring = Some(value.parse::<usize>().map_err(|_| format!("Invalid entry count: {}", value))?);
+            }
+            "--trace-level" => trace_config.parse_level(&args.next().ok_or("--trace-level needs a level")?)?,
+            "--trace-categories" => trace_config.parse_categories(&args.next().ok_or("--trace-categories needs a list")?)?,
+            "--trace-pc" => trace_config.parse_pc_range(&args.next().ok_or("--trace-pc needs a range")?)?,
+            "--trace-ops" => trace_config.parse_opcode_classes(&args.next().ok_or("--trace-ops needs a list")?)?,
+            _ => opts.rom = arg,
+        }
+    }
+
+    let [first, second] = layouts;
+    opts.keys = KeyMap::new(first.as_deref().unwrap_or(KeyMap::FIRST), second.as_deref().unwrap_or(KeyMap::SECOND))?;
+    if let Some(font) = font {
+        profile.font = Font::parse(&font, &profile.font)?;
+    }
+    opts.timing = timing.unwrap_or_else(|| profile.timing.clone()); // the machine's own speed by default
+    match (&mut opts.timing, ipf) {
+        (Timing::Fixed { ipf }, Some(n)) => *ipf = n,
+        (Timing::Vip(_), Some(_)) => return Err("--ipf only applies to fixed timing".to_string()),
+        _ => {}
+    }
+
+    // asking for a trace without a level means "everything"
+    if (trace_file.is_some() || trace_ring.is_some()) && trace_config.level == Level::Off {
+        trace_config.level = Level::Trace;
+    }
     let mut emu = CPU::new();
-    let width = 64;
-    let height = 32;
-    let scale = 10;
-    // println!("Current dir: {:?}", std::env::current_dir().unwrap());
-    emu.load_and_execute("test_roms/6-keypad.ch8".to_string()).unwrap();
+    emu.set_profile(profile)?;
+    emu.set_platform(platform);
+    emu.set_jit(jit);
+    emu.set_stack_config(StackConfig { in_memory: stack.in_memory || stack_in_memory, ..stack })?;
+    emu.set_memory_display(memory_display);
+    emu.set_hybrid(hybrid);
+    if let Some(path) = &trace_file {
+        emu.set_tracer(Tracer::to_file(trace_config, path)?);
+    } else if let Some(capacity) = trace_ring {
+        emu.set_tracer(Tracer::ring(trace_config, capacity));
+    }
+
+    let result = run(&mut emu, opts);
+    if result.is_err() && trace_ring.is_some() {
+        // the ring holds the instructions leading up to the failure
+        for entry in emu.tracer().entries() {
+            eprintln!("{}", entry);
+        }
+    }
+    result
+}
+
+fn run(emu: &mut CPU, opts: Options) -> Result<(), String> {
+    if let Some(frames) = opts.headless_frames {
+        let data = std::fs::read(&opts.rom).map_err(|e| format!("Cannot open {}: {}", opts.rom, e))?;
+        let config = RunConfig { frames, script: opts.script, timing: opts.timing, ..RunConfig::default() };
+        headless::run_on(emu, &data, &config)?;
+        print!("{}", headless::framebuffer_text(emu.display()));
+        println!("hash: {:016x}", headless::framebuffer_hash(emu.display()));
+        return Ok(());
+    }
+
+    emu.load_rom(&opts.rom)?;
+    let display_mode = match opts.display_mode {
+        Some(mode) => mode,
+        None => DisplayMode::for_rom(&opts.rom)?.unwrap_or(DisplayMode::Direct),
+    };
+    let graphics = |protocol| Video::Graphics { protocol, scale: opts.scale };
+    match opts.frontend.as_str() {
+        "window" => window::start(emu, display_mode, &opts.keys, opts.timing),
+        "terminal" => terminal::start(emu, display_mode, &opts.keys, Video::HalfBlocks, opts.timing),
+        "sixel" => terminal::start(emu, display_mode, &opts.keys, graphics(GraphicsProtocol::Sixel), opts.timing),
+        "kitty" => terminal::start(emu, display_mode, &opts.keys, graphics(GraphicsProtocol::Kitty), opts.timing),
+        _ => Err(format!("Unknown frontend: {}", opts.frontend)),
+    }
+}
+
+fn diff(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut files = Vec::new();
+    let mut lines = 5;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let value = args.next().ok_or("--context needs a line count")?;
+                lines = value.parse::<usize>().map_err(|_| format!("Invalid line count: {}", value))?;
+            }
+            _ => files.push(arg),
+        }
+    }
+    let [ours, theirs] = &files[..] else {
+        return Err("diff needs two trace files: OURS REFERENCE".to_string());
+    };
+    let read = |path: &str| {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        trace_diff::parse_trace(&text).map_err(|e| format!("{}: {}", path, e))
+    };
+    let (ours, theirs) = (read(ours)?, read(theirs)?);
+    match trace_diff::first_divergence(&ours, &theirs) {
+        Some(div) => {
+            print!("{}", trace_diff::report(&ours, &theirs, &div, lines));
+            std::process::exit(1);
+        }
+        None => {
+            println!("no divergence");
+            Ok(())
+        }
+    }
+}
+
+fn recompile(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut rom = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().ok_or("-o needs a file")?),
+            _ => rom = Some(arg),
+        }
+    }
+    let rom = rom.ok_or("recompile needs a ROM")?;
+    let data = std::fs::read(&rom).map_err(|e| format!("Cannot open {}: {}", rom, e))?;
+    let analysis = recompile::analyze(&data);
+    let name = std::path::Path::new(&rom).file_name().map_or(rom.clone(), |n| n.to_string_lossy().into_owned());
+    let source = recompile::emit(&data, &analysis, &name);
+    match output {
+        Some(path) => std::fs::write(&path, source).map_err(|e| format!("Cannot write {}: {}", path, e))?,
+        None => print!("{}", source),
+    }
+    let interpreted = analysis.blocks.iter().filter(|b| b.self_modifying).count();
+    eprintln!(
+        "{} blocks compiled, {} self-modifying blocks and {} computed jumps left to the interpreter",
+        analysis.blocks.len() - interpreted,
+        interpreted,
+        analysis.computed_jumps.len()
+    );
+    Ok(())
+}
+
+fn bench(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut rom = None;
+    let mut config = BenchConfig::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--instructions" => {
+                let value = args.next().ok_or("--instructions needs a count")?;
+                config.limit = Limit::Instructions(value.parse::<u64>().map_err(|_| format!("Invalid instruction count: {}", value))?);
+            }
+            "--seconds" => {
+                let value = args.next().ok_or("--seconds needs a duration")?;
+                config.limit = Limit::Seconds(value.parse::<f64>().map_err(|_| format!("Invalid duration: {}", value))?);
+            }
+            "--ipf" => {
+                let value = args.next().ok_or("--ipf needs an instruction count")?;
+                config.ipf = value.parse::<u32>().map_err(|_| format!("Invalid instruction count: {}", value))?;
+            }
+            "--jit" => config.jit = true,
+            _ => rom = Some(arg),
+        }
+    }
+    if config.ipf == 0 {
+        return Err("--ipf must be at least 1".to_string());
+    }
+    let rom = rom.ok_or("bench needs a ROM")?;
+    let data = std::fs::read(&rom).map_err(|e| format!("Cannot open {}: {}", rom, e))?;
+    println!("{}", bench::run(&data, &config)?);
+    Ok(())
+}
+
+// Runs a ROM on the emulated VIP hardware, headless, or checks `CPU` against it.
+fn run_vip(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut rom = None;
+    let mut interpreter = None;
+    let mut monitor = None;
+    let mut ram = 4096;
+    let mut frames = 60;
+    let mut check = None;
+    let read = |path: String| std::fs::read(&path).map_err(|e| format!("Cannot open {}: {}", path, e));
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interpreter" => interpreter = Some(read(args.next().ok_or("--interpreter needs a file")?)?),
+            "--monitor" => monitor = Some(read(args.next().ok_or("--monitor needs a file")?)?),
+            "--ram" => {
+                let value = args.next().ok_or("--ram needs a size")?;
+                ram = value.parse::<usize>().map_err(|_| format!("Invalid RAM size: {}", value))?;
+            }
+            "--frames" => {
+                let value = args.next().ok_or("--frames needs a frame count")?;
+                frames = value.parse::<u32>().map_err(|_| format!("Invalid frame count: {}", value))?;
+            }
+            "--check" => {
+                let value = args.next().ok_or("--check needs an instruction count")?;
+                check = Some(value.parse::<u64>().map_err(|_| format!("Invalid instruction count: {}", value))?);
+            }
+            _ => rom = Some(arg),
+        }
+    }
+    let data = read(rom.ok_or("vip needs a ROM")?)?;
+    let interpreter = interpreter.ok_or("vip needs the interpreter: --interpreter FILE")?;
+    let mut machine = Vip::new(&interpreter, monitor.as_deref(), ram, &data)?;
+
+    if let Some(instructions) = check {
+        let mut cpu = vip::reference_cpu(&data)?;
+        return match vip::check(&mut machine, &mut cpu, instructions)? {
+            Some(report) => {
+                println!("{}", report);
+                std::process::exit(1);
+            }
+            None => {
+                println!("no divergence in {} instructions", instructions);
+                Ok(())
+            }
+        };
+    }
+    for _ in 0..frames {
+        machine.run_frame()?;
+    }
+    print!("{}", headless::framebuffer_text(&machine.display()));
+    println!("hash: {:016x}", headless::framebuffer_hash(&machine.display()));
     Ok(())
-        // window.
 }
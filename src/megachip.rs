@@ -0,0 +1,228 @@
+// MegaChip8: a 256x192 true-colour screen and digitised sound on top of CHIP-8.
+//
+// 0011 turns MegaChip mode on and 0010 back off. While it is on, DXYN draws a
+// sprite of the size set by 03NN/04NN, one byte per pixel, each byte an index
+// into a palette of 32-bit ARGB colours that 02NN loads from memory. Index 0
+// is transparent. Sprites are blended into a back buffer with the mode set by
+// 080N, and VF is set when one covers a pixel last drawn with the collision
+// colour index from 09NN (never 0, which is nothing drawn). 00E0 shows the back buffer and clears it, so ROMs
+// draw a whole frame and then present it. 05NN fades the whole screen.
+//
+// 060N plays the sample at I, once with N=1 or looping with N=0, and 0700
+// stops it. A sample is a 2-byte rate in Hz, a 3-byte length and a byte of
+// padding, all big-endian, then that many unsigned 8-bit samples.
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 192;
+const SAMPLE_HEADER: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blend {
+    Normal,   // the sprite replaces what is there
+    Quarter,  // 25% sprite, 75% background
+    Half,     // 50% of each
+    Add,      // channels added, saturating
+    Multiply, // channels multiplied
+}
+
+impl Blend {
+    // 080N; unknown modes draw normally
+    pub fn from_mode(n: u8) -> Self {
+        match n {
+            1 => Blend::Quarter,
+            2 => Blend::Half,
+            3 => Blend::Add,
+            4 => Blend::Multiply,
+            _ => Blend::Normal,
+        }
+    }
+
+    fn mix(self, dst: u32, src: u32) -> u32 {
+        let channel = |c: u32, shift: u32| (c >> shift) & 0xFF;
+        let each = |f: &dyn Fn(u32, u32) -> u32| {
+            [16, 8, 0].iter().fold(0xFF00_0000, |out, &s| out | f(channel(dst, s), channel(src, s)).min(0xFF) << s)
+        };
+        match self {
+            Blend::Normal => src,
+            Blend::Quarter => each(&|d, s| (d * 3 + s) / 4),
+            Blend::Half => each(&|d, s| (d + s) / 2),
+            Blend::Add => each(&|d, s| d + s),
+            Blend::Multiply => each(&|d, s| d * s / 255),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    rate: u32,
+    data: Vec<u8>,
+    looping: bool,
+    position: f64, // in samples
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MegaChip {
+    enabled: bool,    // MegaChip mode, as opposed to plain CHIP-8 on the same machine
+    palette: [u32; 256],
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8,
+    blend: Blend,
+    collision: u8,
+    back: Vec<u32>,   // being drawn, ARGB
+    indices: Vec<u8>, // palette index last drawn at each pixel, for collisions
+    front: Vec<u32>,  // last shown by 00E0
+    presented: bool,  // `front` or the alpha changed since `take_presented`
+    sample: Option<Sample>,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MegaChip {
+    pub fn new() -> Self {
+        MegaChip {
+            enabled: false,
+            palette: [0; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xFF,
+            blend: Blend::Normal,
+            collision: 0,
+            back: vec![0; WIDTH * HEIGHT],
+            indices: vec![0; WIDTH * HEIGHT],
+            front: vec![0; WIDTH * HEIGHT],
+            presented: true,
+            sample: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // 0011 and 0010; the screen changes over completely either way
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.presented = true;
+    }
+
+    // 02NN: `count` ARGB colours from `mem`, as palette entries 1 to `count`
+    pub fn load_palette(&mut self, mem: &[u8], count: u8) {
+        for (n, colour) in mem.chunks(4).take(count as usize).enumerate() {
+            let mut argb = [0; 4];
+            argb[..colour.len()].copy_from_slice(colour);
+            self.palette[n + 1] = u32::from_be_bytes(argb);
+        }
+    }
+
+    // 03NN and 04NN, where 0 means 256
+    pub fn set_sprite_width(&mut self, nn: u8) {
+        self.sprite_width = if nn == 0 { 256 } else { nn as usize };
+    }
+
+    pub fn set_sprite_height(&mut self, nn: u8) {
+        self.sprite_height = if nn == 0 { 256 } else { nn as usize };
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+        self.presented = true;
+    }
+
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+
+    pub fn set_collision(&mut self, index: u8) {
+        self.collision = index;
+    }
+
+    // the number of bytes DXYN reads from I
+    pub fn sprite_len(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    // Draws the sprite in `data` with its top left corner at `x`, `y`, clipped
+    // at the edges. True if it covered the collision colour.
+    pub fn draw(&mut self, data: &[u8], x: usize, y: usize) -> bool {
+        let mut collision = false;
+        for (row, line) in data.chunks(self.sprite_width.max(1)).take(self.sprite_height).enumerate() {
+            if y + row >= HEIGHT {
+                break;
+            }
+            for (column, &index) in line.iter().enumerate().filter(|&(c, &i)| i != 0 && x + c < WIDTH) {
+                let at = (y + row) * WIDTH + x + column;
+                collision |= self.indices[at] != 0 && self.indices[at] == self.collision;
+                self.indices[at] = index;
+                self.back[at] = self.blend.mix(self.back[at], self.palette[index as usize]);
+            }
+        }
+        collision
+    }
+
+    // 00E0: what has been drawn goes on screen, and drawing starts again on black
+    pub fn present(&mut self) {
+        std::mem::swap(&mut self.front, &mut self.back);
+        self.back.fill(0);
+        self.indices.fill(0);
+        self.presented = true;
+    }
+
+    // true if the picture changed since the last call
+    pub fn take_presented(&mut self) -> bool {
+        std::mem::take(&mut self.presented)
+    }
+
+    // the picture as 0xRRGGBB, faded by the screen alpha
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        let argb = self.front[y * WIDTH + x];
+        let alpha = self.alpha as u32;
+        [16, 8, 0].iter().fold(0, |out, &s| out | (((argb >> s) & 0xFF) * alpha / 255) << s)
+    }
+
+    // 060N: starts the sample whose header is at the start of `mem`
+    pub fn play(&mut self, mem: &[u8], looping: bool) {
+        if mem.len() < SAMPLE_HEADER {
+            return;
+        }
+        let rate = (mem[0] as u32) << 8 | mem[1] as u32;
+        let len = (mem[2] as usize) << 16 | (mem[3] as usize) << 8 | mem[4] as usize;
+        let data = mem[SAMPLE_HEADER..].iter().take(len).copied().collect();
+        self.sample = Some(Sample { rate, data, looping, position: 0.0 });
+    }
+
+    pub fn stop(&mut self) {
+        self.sample = None;
+    }
+
+    pub fn playing(&self) -> bool {
+        self.sample.is_some()
+    }
+
+    // Fills `out` with the playing sample, resampled to `rate` Hz, as signed
+    // 16-bit audio. Whatever is left after the sample ends is silence.
+    pub fn mix(&mut self, out: &mut [i16], rate: u32) {
+        out.fill(0);
+        let Some(sample) = &mut self.sample else {
+            return;
+        };
+        let step = sample.rate as f64 / rate as f64;
+        for value in out.iter_mut() {
+            let mut at = sample.position as usize;
+            if at >= sample.data.len() {
+                if !sample.looping || sample.data.is_empty() {
+                    self.sample = None;
+                    return;
+                }
+                sample.position %= sample.data.len() as f64; // a fast, short sample can wrap more than once
+                at = sample.position as usize;
+            }
+            *value = (sample.data[at] as i16 - 128) << 8;
+            sample.position += step;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Presentation-side filters that reduce XOR sprite flicker.
+//
+// These only ever touch the RGB buffer handed to the frontend; the emulated
+// `Framebuffer` (and therefore DXYN collision detection) is left alone.
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayMode {
+    Direct,                   // show every intermediate state as-is
+    Phosphor { decay: f32 },  // lit pixels fade by `decay` per frame once switched off
+    Blend { frames: usize },  // average of the last N presented frames
+}
+
+impl DisplayMode {
+    // accepts "direct", "phosphor", "phosphor:<decay>", "blend" or "blend:<frames>"
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        match name {
+            "direct" => Ok(DisplayMode::Direct),
+            "phosphor" => {
+                let decay = match arg {
+                    Some(a) => a.parse::<f32>().map_err(|_| format!("Invalid phosphor decay: {}", a))?,
+                    None => 0.6,
+                };
+                if !(0.0..1.0).contains(&decay) {
+                    return Err(format!("Phosphor decay must be in [0, 1): {}", decay));
+                }
+                Ok(DisplayMode::Phosphor { decay })
+            }
+            "blend" => {
+                let frames = match arg {
+                    Some(a) => a.parse::<usize>().map_err(|_| format!("Invalid blend frame count: {}", a))?,
+                    None => 3,
+                };
+                if frames == 0 {
+                    return Err("Blend frame count must be at least 1".to_string());
+                }
+                Ok(DisplayMode::Blend { frames })
+            }
+            _ => Err(format!("Unknown display mode: {}", s)),
+        }
+    }
+
+    // The mode saved for a ROM in a file next to it, with ".display" added to
+    // its name (game.ch8.display), so each game can keep the filter it needs.
+    pub fn for_rom(rom: &str) -> Result<Option<Self>, String> {
+        let path = format!("{}.display", rom);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(text.trim()).map(Some).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Cannot read {}: {}", path, e)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DisplayFilter {
+    mode: DisplayMode,
+    intensity: Vec<[f32; 3]>,    // per-pixel red, green and blue for phosphor mode
+    history: VecDeque<Vec<u32>>, // most recent frame at the back, for blend mode
+}
+
+impl DisplayFilter {
+    pub fn new(mode: DisplayMode) -> Self {
+        DisplayFilter {
+            mode,
+            intensity: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn mode(&self) -> DisplayMode {
+        self.mode
+    }
+
+    // Rewrites an XRGB buffer in place. Each colour channel fades or is
+    // averaged on its own, so CHIP-8X and MegaChip colours survive. Call once
+    // per presented frame.
+    pub fn apply(&mut self, buffer: &mut [u32]) {
+        match self.mode {
+            DisplayMode::Direct => {}
+            DisplayMode::Phosphor { decay } => {
+                if self.intensity.len() != buffer.len() {
+                    self.intensity = vec![[0.0; 3]; buffer.len()];
+                }
+                for (px, level) in buffer.iter_mut().zip(self.intensity.iter_mut()) {
+                    // a channel lights at once and fades no faster than `decay`
+                    for (c, l) in channels(*px).into_iter().zip(level.iter_mut()) {
+                        *l = (c as f32 / 255.0).max(*l * decay);
+                    }
+                    *px = rgb(level.map(|l| (l * 255.0) as u32));
+                }
+            }
+            DisplayMode::Blend { frames } => {
+                if self.history.front().is_some_and(|f| f.len() != buffer.len()) {
+                    self.history.clear();
+                }
+                self.history.push_back(buffer.to_vec());
+                while self.history.len() > frames {
+                    self.history.pop_front();
+                }
+                let count = self.history.len() as u32;
+                for (i, px) in buffer.iter_mut().enumerate() {
+                    let mut sum = [0; 3];
+                    for frame in &self.history {
+                        for (s, c) in sum.iter_mut().zip(channels(frame[i])) {
+                            *s += c;
+                        }
+                    }
+                    *px = rgb(sum.map(|s| s / count));
+                }
+            }
+        }
+    }
+}
+
+fn channels(px: u32) -> [u32; 3] {
+    [(px >> 16) & 0xFF, (px >> 8) & 0xFF, px & 0xFF]
+}
+
+fn rgb([r, g, b]: [u32; 3]) -> u32 {
+    (r.min(0xFF) << 16) | (g.min(0xFF) << 8) | b.min(0xFF)
+}
@@ -0,0 +1,105 @@
+// Machines that ran CHIP-8, as far as a ROM can tell them apart: where
+// programs load, how much RAM there is for them, the FX29 digits and where
+// they live, the height of the display and how fast instructions run.
+// Everything else comes from the quirks, the stack and the platform.
+
+use crate::cpu::{CODE_SIZE, HEIGHT, MAX_HEIGHT};
+use crate::font::{self, Font};
+use crate::timing::{Timing, VipTiming, DEFAULT_IPF};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineProfile {
+    pub name: &'static str,
+    pub start: usize,      // where programs load and start, unless the platform moves them
+    pub memory: usize,     // bytes of RAM, which a ROM has to fit in after `start`; 4K at most
+    pub font: Font,
+    pub font_start: usize, // where the small font goes, with the big one straight after
+    pub height: usize,     // display lines; the display is always 64 pixels wide
+    pub timing: Timing,    // what the frontends run unless told otherwise
+    pub detect_hires: bool, // two-page HIRES ROMs get the 64x64 display, as on the VIP
+}
+
+impl Default for MachineProfile {
+    fn default() -> Self {
+        Self::chip8()
+    }
+}
+
+impl MachineProfile {
+    pub const NAMES: [&'static str; 4] = ["chip8", "vip", "dream6800", "eti660"];
+
+    // "chip8", "vip", "dream6800" or "eti660"
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "chip8" => Ok(Self::chip8()),
+            "vip" => Ok(Self::vip()),
+            "dream6800" => Ok(Self::dream_6800()),
+            "eti660" => Ok(Self::eti_660()),
+            _ => Err(format!("Unknown machine: {}", s)),
+        }
+    }
+
+    // what this emulator has always done: a VIP memory map at a flat 600 instructions per second
+    pub fn chip8() -> Self {
+        MachineProfile {
+            name: "chip8",
+            start: 0x200,
+            memory: 4096,
+            font: Font::default(),
+            font_start: font::DEFAULT_START,
+            height: HEIGHT,
+            timing: Timing::default(),
+            detect_hires: true,
+        }
+    }
+
+    // the COSMAC VIP with 4K, running at the speed of its interpreter
+    pub fn vip() -> Self {
+        MachineProfile { name: "vip", font: Font::named("vip").unwrap(), timing: Timing::Vip(VipTiming::new()), ..Self::chip8() }
+    }
+
+    // Michael Bauer's DREAM 6800, running CHIPOS, with its RAM expanded to 4K.
+    // There are no cycle timings for CHIPOS yet.
+    pub fn dream_6800() -> Self {
+        MachineProfile {
+            name: "dream6800",
+            font: Font::named("dream6800").unwrap(),
+            timing: Timing::Fixed { ipf: DEFAULT_IPF },
+            detect_hires: false,
+            ..Self::chip8()
+        }
+    }
+
+    // The ETI-660, whose interpreter and display take up memory up to 0x600,
+    // with a 64x48 display. There are no cycle timings for it yet.
+    pub fn eti_660() -> Self {
+        MachineProfile {
+            name: "eti660",
+            font: Font::named("eti660").unwrap(),
+            start: 0x600,
+            height: 48,
+            timing: Timing::Fixed { ipf: DEFAULT_IPF },
+            detect_hires: false,
+            ..Self::chip8()
+        }
+    }
+
+    // whether a CPU can be set up like this
+    pub fn check(&self) -> Result<(), String> {
+        if self.memory > CODE_SIZE {
+            // past 4K, 12-bit addresses could not reach it, and save states have no room for it
+            return Err(format!("{}: {} bytes of RAM is more than the {} CHIP-8 can address", self.name, self.memory, CODE_SIZE));
+        }
+        if self.start >= self.memory || !self.start.is_multiple_of(2) {
+            return Err(format!("{}: programs cannot start at 0x{:03X}", self.name, self.start));
+        }
+        let sizes = (self.font.small.len(), self.font.big.as_ref().map_or(font::BIG_SIZE, Vec::len));
+        if sizes != (font::SMALL_SIZE, font::BIG_SIZE) || self.font_start + self.font.bytes().len() > self.memory {
+            return Err(format!("{}: the font does not fit at 0x{:03X}", self.name, self.font_start));
+        }
+        if self.height == 0 || self.height > MAX_HEIGHT {
+            return Err(format!("{}: displays are 1 to {} lines, not {}", self.name, MAX_HEIGHT, self.height));
+        }
+        Ok(())
+    }
+}
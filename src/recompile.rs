@@ -0,0 +1,263 @@
+// Static recompiler: turns the reachable code of a ROM into a Rust module.
+//
+// `analyze` follows control flow from 0x200 (jumps, calls and their return
+// sites, both ways out of skips) and splits what it finds into basic blocks.
+// `emit` writes each block as a function that works on the CPU's public state
+// API, plus a `run` dispatcher with the same contract as `CPU::run`.
+//
+// Some code is left to the interpreter:
+// - code only reachable through BNNN, whose target is only known at run time
+// - blocks overlapping a write the analysis can see (ANNN then FX33/FX55 in one
+//   block), i.e. self-modifying code
+// - any compiled block whose bytes differ from the ROM when it is about to
+//   run, which catches the writes the analysis can't see
+//
+// Quirk-dependent instructions and anything touching the display, keypad,
+// timers or memory go through `CPU::execute_at`, so the generated code works
+// with whatever quirks the CPU is set up with.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use crate::decode::{decode, Op};
+use crate::jit::ends_block;
+
+const START: usize = 0x200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    pub ops: Vec<(usize, Op)>, // address and instruction
+    pub self_modifying: bool,  // overlaps a write found by the analysis
+}
+
+impl Block {
+    pub fn end(&self) -> usize {
+        self.start + self.ops.len() * 2
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Analysis {
+    pub blocks: Vec<Block>,
+    pub computed_jumps: Vec<usize>, // addresses of BNNN instructions
+}
+
+fn op_at(rom: &[u8], addr: usize) -> Option<Op> {
+    let offset = addr.checked_sub(START)?;
+    if offset + 1 >= rom.len() {
+        return None;
+    }
+    Some(decode((rom[offset] as u16) << 8 | rom[offset + 1] as u16))
+}
+
+// where control can go after the instruction at `addr`, as far as is known statically
+fn successors(addr: usize, op: Op) -> Vec<usize> {
+    match op {
+        Op::Jump(nnn) => vec![nnn as usize],
+        Op::Call(nnn) => vec![nnn as usize, addr + 2],
+        Op::Return | Op::JumpOffset(..) | Op::Invalid(_) => vec![],
+        Op::SkipEqImm(..) | Op::SkipNeImm(..) | Op::SkipEq(..) | Op::SkipNe(..) | Op::SkipKey(_) | Op::SkipNoKey(_) => {
+            vec![addr + 2, addr + 4]
+        }
+        _ => vec![addr + 2],
+    }
+}
+
+pub fn analyze(rom: &[u8]) -> Analysis {
+    // every reachable instruction, and the ones that start a block
+    let mut reachable = BTreeMap::new();
+    let mut leaders = BTreeSet::from([START]);
+    let mut computed_jumps = Vec::new();
+    let mut work = vec![START];
+    while let Some(addr) = work.pop() {
+        if reachable.contains_key(&addr) {
+            continue;
+        }
+        let Some(op) = op_at(rom, addr) else { continue };
+        reachable.insert(addr, op);
+        if let Op::JumpOffset(..) = op {
+            computed_jumps.push(addr);
+        }
+        let next = successors(addr, op);
+        if ends_block(op) {
+            leaders.extend(&next);
+        }
+        work.extend(next);
+    }
+    computed_jumps.sort();
+
+    let mut blocks = Vec::new();
+    let mut writes = Vec::new(); // byte ranges written at statically known addresses
+    for &start in leaders.iter().filter(|a| reachable.contains_key(a)) {
+        let mut ops = Vec::new();
+        let mut addr = start;
+        let mut index = None; // I, while it is known
+        while let Some(&op) = reachable.get(&addr) {
+            if addr != start && leaders.contains(&addr) {
+                break;
+            }
+            ops.push((addr, op));
+            match op {
+                Op::SetI(nnn) => index = Some(nnn as usize),
+                Op::Bcd(_) => writes.extend(index.map(|i| i..i + 3)),
+                Op::Store(x) => writes.extend(index.map(|i| i..i + x as usize + 1)),
+                Op::AddI(_) | Op::Font(_) | Op::BigFont(_) | Op::Load(_) => index = None,
+                _ => {}
+            }
+            addr += 2;
+            if ends_block(op) {
+                break;
+            }
+        }
+        blocks.push(Block { start, ops, self_modifying: false });
+    }
+    for block in &mut blocks {
+        block.self_modifying = writes.iter().any(|w| w.start < block.end() && block.start < w.end);
+    }
+    Analysis { blocks, computed_jumps }
+}
+
+// straight-line code for `op`, or None if it has to go through the interpreter
+fn inline(op: Op) -> Option<String> {
+    Some(match op {
+        Op::SetImm(x, nn) => format!("v[0x{:X}] = 0x{:02X};", x, nn),
+        Op::AddImm(x, nn) => format!("v[0x{:X}] = v[0x{:X}].wrapping_add(0x{:02X});", x, x, nn),
+        Op::Set(x, y) => format!("v[0x{:X}] = v[0x{:X}];", x, y),
+        Op::Add(x, y) => format!(
+            "let (sum, carry) = v[0x{:X}].overflowing_add(v[0x{:X}]); v[0x{:X}] = sum; v[0xF] = carry as u8;",
+            x, y, x
+        ),
+        Op::Sub(x, y) => format!(
+            "let (diff, borrow) = v[0x{:X}].overflowing_sub(v[0x{:X}]); v[0x{:X}] = diff; v[0xF] = !borrow as u8;",
+            x, y, x
+        ),
+        Op::SubReverse(x, y) => format!(
+            "let (diff, borrow) = v[0x{:X}].overflowing_sub(v[0x{:X}]); v[0x{:X}] = diff; v[0xF] = !borrow as u8;",
+            y, x, x
+        ),
+        _ => return None,
+    })
+}
+
+fn condition(op: Op) -> Option<String> {
+    Some(match op {
+        Op::SkipEqImm(x, nn) => format!("cpu.registers()[0x{:X}] == 0x{:02X}", x, nn),
+        Op::SkipNeImm(x, nn) => format!("cpu.registers()[0x{:X}] != 0x{:02X}", x, nn),
+        Op::SkipEq(x, y) => format!("cpu.registers()[0x{:X}] == cpu.registers()[0x{:X}]", x, y),
+        Op::SkipNe(x, y) => format!("cpu.registers()[0x{:X}] != cpu.registers()[0x{:X}]", x, y),
+        _ => return None,
+    })
+}
+
+// `op` as a Rust expression, with hex operands
+fn op_expr(op: Op) -> String {
+    let debug = format!("{:X?}", op); // e.g. "Draw(0, 1, F)"
+    match debug.split_once('(') {
+        Some((name, args)) => {
+            let args: Vec<String> = args.trim_end_matches(')').split(", ").map(|a| format!("0x{}", a)).collect();
+            format!("Op::{}({})", name, args.join(", "))
+        }
+        None => format!("Op::{}", debug),
+    }
+}
+
+fn emit_block(out: &mut String, rom: &[u8], block: &Block) {
+    let opcode = |addr: usize| format!("// {:03X}: {:02X}{:02X}", addr, rom[addr - START], rom[addr - START + 1]);
+    let _ = writeln!(out, "// 0x{:03X}-0x{:03X}", block.start, block.end() - 1);
+    let _ = writeln!(out, "fn block_{:03x}(cpu: &mut CPU) -> Result<(), String> {{", block.start);
+    let mut inlined = 0; // instructions run here rather than by `execute_at`
+    let mut registers = false; // whether `v` is currently borrowed from `cpu`
+    let (last_addr, last) = *block.ops.last().unwrap();
+    let body = if ends_block(last) { &block.ops[..block.ops.len() - 1] } else { &block.ops[..] };
+    for &(addr, op) in body {
+        if let Some(code) = inline(op) {
+            if !registers {
+                out.push_str("    let v = cpu.registers_mut();\n");
+                registers = true;
+            }
+            let _ = writeln!(out, "    {} {}", code, opcode(addr));
+        } else if let Op::SetI(nnn) = op {
+            let _ = writeln!(out, "    cpu.set_index(0x{:03X}); {}", nnn, opcode(addr));
+            registers = false;
+        } else {
+            let _ = writeln!(out, "    cpu.execute_at(0x{:03X}, {})?; {}", addr, op_expr(op), opcode(addr));
+            registers = false;
+            continue;
+        }
+        inlined += 1;
+    }
+    if !ends_block(last) {
+        let _ = writeln!(out, "    cpu.advance(0x{:03X}, {});", block.end(), inlined);
+    } else if let Op::Jump(nnn) = last {
+        let _ = writeln!(out, "    cpu.advance(0x{:03X}, {}); {}", nnn, inlined + 1, opcode(last_addr));
+    } else if let Some(cond) = condition(last) {
+        let _ = writeln!(
+            out,
+            "    let next = if {} {{ 0x{:03X} }} else {{ 0x{:03X} }}; {}",
+            cond,
+            last_addr + 4,
+            last_addr + 2,
+            opcode(last_addr)
+        );
+        let _ = writeln!(out, "    cpu.advance(next, {});", inlined + 1);
+    } else {
+        let _ = writeln!(out, "    cpu.advance(0x{:03X}, {});", last_addr, inlined);
+        let _ = writeln!(out, "    cpu.execute_at(0x{:03X}, {})?; {}", last_addr, op_expr(last), opcode(last_addr));
+    }
+    out.push_str("    Ok(())\n}\n");
+}
+
+// Rust source for a module running `rom`; `name` only goes into the header comment
+pub fn emit(rom: &[u8], analysis: &Analysis, name: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "// Recompiled from {} by `chip8-emulator recompile`. Do not edit.", name);
+    out.push_str("//\n");
+    let compiled: Vec<&Block> = analysis.blocks.iter().filter(|b| !b.self_modifying).collect();
+    let _ = writeln!(out, "// {} blocks compiled.", compiled.len());
+    for block in analysis.blocks.iter().filter(|b| b.self_modifying) {
+        let _ = writeln!(out, "// 0x{:03X}-0x{:03X} is written by the ROM and runs on the interpreter.", block.start, block.end() - 1);
+    }
+    for addr in &analysis.computed_jumps {
+        let _ = writeln!(out, "// BNNN at 0x{:03X} jumps to code that runs on the interpreter.", addr);
+    }
+    out.push_str("\nuse chip8_emulator::cpu::CPU;\nuse chip8_emulator::decode::Op;\n\n");
+
+    let _ = writeln!(out, "const ROM: [u8; {}] = [", rom.len());
+    for line in rom.chunks(16) {
+        let bytes: Vec<String> = line.iter().map(|b| format!("0x{:02X}", b)).collect();
+        let _ = writeln!(out, "    {},", bytes.join(", "));
+    }
+    out.push_str("];\n\n");
+
+    out.push_str("// whether the code at start..end is still what the ROM loaded there\n");
+    out.push_str("fn unchanged(cpu: &CPU, start: usize, end: usize) -> bool {\n");
+    let _ = writeln!(out, "    (start..end).all(|addr| cpu.read_mem(addr) == ROM[addr - 0x{:03X}])", START);
+    out.push_str("}\n\n");
+
+    out.push_str("// runs `count` instructions, like `CPU::run`\n");
+    out.push_str("pub fn run(cpu: &mut CPU, count: u32) -> Result<(), String> {\n");
+    out.push_str("    let mut left = count as usize;\n    while left > 0 {\n        left -= match cpu.pc() {\n");
+    for block in &compiled {
+        let _ = writeln!(
+            out,
+            "            0x{:03X} if left >= {} && unchanged(cpu, 0x{:03X}, 0x{:03X}) => block_{:03x}(cpu).map(|_| {})?,",
+            block.start,
+            block.ops.len(),
+            block.start,
+            block.end(),
+            block.start,
+            block.ops.len()
+        );
+    }
+    out.push_str("            _ => cpu.execute().map(|_| 1)?,\n        };\n    }\n    Ok(())\n}\n\n");
+
+    out.push_str("// runs one 60Hz frame, like `CPU::run_frame`\n");
+    out.push_str("pub fn run_frame(cpu: &mut CPU, ipf: u32) -> Result<(), String> {\n");
+    out.push_str("    run(cpu, ipf)?;\n    cpu.tick_timers();\n    Ok(())\n}\n");
+
+    for block in compiled {
+        out.push('\n');
+        emit_block(&mut out, rom, block);
+    }
+    out
+}
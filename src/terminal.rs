@@ -0,0 +1,200 @@
+// Terminal frontend for machines without a graphical session (e.g. over SSH).
+// Two pixel rows share one character cell using the upper half-block glyph,
+// with the top pixel as foreground colour and the bottom one as background.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags};
+use crossterm::{cursor, execute, queue, terminal};
+use crate::cpu::CPU;
+use crate::graphics::GraphicsProtocol;
+use crate::keymap::KeyMap;
+use crate::phosphor::{DisplayFilter, DisplayMode};
+use crate::timing::{FramePacer, Timing};
+
+// Most terminals only report key presses (plus auto-repeat), so a key counts as
+// held until this long after its last press or repeat event.
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Video {
+    HalfBlocks,                                               // works in any colour terminal
+    Graphics { protocol: GraphicsProtocol, scale: usize },    // pixel-exact bitmap, status line on top
+}
+
+// Draws `buffer` (0xRRGGBB per pixel, `width` x `height`) as rows of half blocks.
+// An odd final row is paired with black.
+pub fn render_half_blocks(buffer: &[u32], width: usize, height: usize) -> String {
+    let mut out = String::with_capacity(width * height.div_ceil(2) * 40);
+    for y in (0..height).step_by(2) {
+        let mut last = None;
+        for x in 0..width {
+            let top = buffer[y * width + x];
+            let bottom = if y + 1 < height { buffer[(y + 1) * width + x] } else { 0 };
+            if last != Some((top, bottom)) {
+                out.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                    top >> 16, (top >> 8) & 0xFF, top & 0xFF,
+                    bottom >> 16, (bottom >> 8) & 0xFF, bottom & 0xFF,
+                ));
+                last = Some((top, bottom));
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+    out
+}
+
+// Puts the terminal into raw mode on the alternate screen and undoes it on drop,
+// so an emulation error still leaves a usable shell behind.
+struct RawTerminal {
+    enhanced_keys: bool,
+}
+
+impl RawTerminal {
+    fn enter() -> Result<Self, String> {
+        terminal::enable_raw_mode().map_err(|e| format!("Cannot enable raw mode: {}", e))?;
+        let mut out = io::stdout();
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide).map_err(|e| e.to_string())?;
+        // terminals implementing the kitty keyboard protocol can report real key releases
+        let enhanced_keys = terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && execute!(out, event::PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)).is_ok();
+        Ok(RawTerminal { enhanced_keys })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.enhanced_keys {
+            let _ = execute!(out, event::PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Tracks which keys are held, either from real release events or by timing
+// out presses. Keys 0x10-0x1F are the CHIP-8X second keypad.
+struct Keypad {
+    last_seen: [Option<Instant>; 32],
+    released: [bool; 32],
+}
+
+impl Keypad {
+    fn new() -> Self {
+        Keypad { last_seen: [None; 32], released: [false; 32] }
+    }
+
+    // the first keypad, or the second with `offset` 16
+    fn state(&self, now: Instant, enhanced_keys: bool, offset: usize) -> [bool; 16] {
+        let mut keypad = [false; 16];
+        for (k, down) in keypad.iter_mut().enumerate() {
+            *down = match self.last_seen[offset + k] {
+                Some(_) if enhanced_keys => !self.released[offset + k],
+                Some(t) => now.duration_since(t) < KEY_HOLD,
+                None => false,
+            };
+        }
+        keypad
+    }
+}
+
+// returns false once the user asks to quit (Esc or Ctrl-C)
+fn poll_input(keypad: &mut Keypad, keys: &KeyMap) -> Result<bool, String> {
+    while event::poll(Duration::ZERO).map_err(|e| e.to_string())? {
+        let Event::Key(key) = event::read().map_err(|e| e.to_string())? else {
+            continue;
+        };
+        match key.code {
+            KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+            KeyCode::Char(c) => {
+                if let Some((pad, chip8_key)) = keys.lookup(c) {
+                    let k = pad * 16 + chip8_key as usize;
+                    if key.kind == KeyEventKind::Release {
+                        keypad.released[k] = true;
+                    } else {
+                        keypad.last_seen[k] = Some(Instant::now());
+                        keypad.released[k] = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(true)
+}
+
+pub fn start(cpu: &mut CPU, display_mode: DisplayMode, keys: &KeyMap, video: Video, mut timing: Timing) -> Result<(), String> {
+    let raw = RawTerminal::enter()?;
+    let mut out = io::stdout();
+
+    let mut display_filter = DisplayFilter::new(display_mode);
+    let (width, height) = cpu.output_size();
+    let mut buffer = vec![0u32; width * height]; // raw display; only changed rows are updated
+    let mut image = buffer.clone(); // what is shown, after the filter
+    let mut keypad = Keypad::new();
+    let mut last_frame = String::new();
+    let mut last_image: Option<Vec<u32>> = None; // last bitmap sent in graphics mode
+
+    let mut pacer = FramePacer::new();
+    let mut speed_window = Instant::now();
+    let mut speed_start = cpu.cycle();
+    let mut speed: u64 = 0; // measured instructions per second
+
+    // one iteration per 60 Hz frame: input, a frame of instructions and timers, redraw
+    loop {
+        let now = Instant::now();
+        if !poll_input(&mut keypad, keys)? {
+            break;
+        }
+        cpu.set_keypad(keypad.state(now, raw.enhanced_keys, 0));
+        cpu.set_keypad2(keypad.state(now, raw.enhanced_keys, 16));
+
+        timing.run_frame(cpu)?;
+
+        if now.duration_since(speed_window) >= Duration::from_secs(1) {
+            speed = cpu.cycle() - speed_start;
+            speed_start = cpu.cycle();
+            speed_window = now;
+        }
+
+        // the filter keeps changing the picture while pixels fade, even without new draws
+        let changed = cpu.display_flag || display_filter.mode() != DisplayMode::Direct;
+        cpu.update_display_buffer(&mut buffer);
+        image.copy_from_slice(&buffer);
+        display_filter.apply(&mut image);
+        let status = format!(
+            "\x1b[2KPC 0x{:03X}  {} ips  sound {}  (Esc to quit)",
+            cpu.pc(),
+            speed,
+            if cpu.sound_timer() > 0 { "ON " } else { "off" },
+        );
+        let mut frame = String::new();
+        match video {
+            Video::HalfBlocks => {
+                frame = render_half_blocks(&image, width, height);
+                frame.push_str(&status);
+            }
+            Video::Graphics { protocol, scale } => {
+                frame.push_str(&status);
+                if (changed || last_image.is_none()) && last_image.as_ref() != Some(&image) {
+                    frame.push_str("\x1b[2;1H"); // image goes below the status line
+                    frame.push_str(&protocol.encode(&image, width, height, scale));
+                    last_image = Some(image.clone());
+                }
+            }
+        }
+        if frame != last_frame {
+            queue!(out, cursor::MoveTo(0, 0)).map_err(|e| e.to_string())?;
+            out.write_all(frame.as_bytes()).map_err(|e| e.to_string())?;
+            out.flush().map_err(|e| e.to_string())?;
+            last_frame = frame;
+        }
+
+        pacer.wait();
+    }
+    Ok(())
+}
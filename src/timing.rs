@@ -0,0 +1,204 @@
+// COSMAC VIP timing model.
+//
+// On the VIP the CHIP-8 interpreter runs on a CDP1802 at 1.7609 MHz, where a
+// machine cycle is 8 clocks (~4.54 us), giving 3668 machine cycles per 60 Hz
+// frame. The CDP1861 steals 1024 of those for display DMA and the interrupt
+// routine (which also decrements the timers) takes some more. CHIP-8
+// instructions cost very different amounts of the remainder, and DXYN waits
+// for the next vertical interrupt before drawing.
+//
+// Costs below are machine cycles after Laurence Scotford's annotated
+// disassembly of the VIP interpreter. Data-dependent loops (00E0, DXYN, FX33,
+// FX55/FX65) use the average cost per iteration.
+//
+// Frontends drive either model one 60 Hz frame at a time through
+// `Timing::run_frame`, and `FramePacer` keeps those frames in step with the
+// wall clock. How much runs in a frame never depends on the host. Both skip
+// over idle loops (see `CPU::idle_loop`) with the cycles they would have cost,
+// so a ROM waiting on a timer or a key leaves the host idle until the next frame.
+
+use std::time::{Duration, Instant};
+use crate::cpu::{IdleLoop, CPU};
+
+pub const CYCLES_PER_FRAME: u32 = 3668;
+const DISPLAY_DMA_CYCLES: u32 = 1024;
+const INTERRUPT_CYCLES: u32 = 106;
+const FETCH_CYCLES: u32 = 40; // fetch, decode and dispatch of every instruction
+
+// machine cycles `instr` will take when executed on `cpu` in its current state
+pub fn vip_cycles(cpu: &CPU, instr: u16) -> u32 {
+    let v = cpu.registers();
+    let x = ((instr & 0x0F00) >> 8) as usize;
+    let y = ((instr & 0x00F0) >> 4) as usize;
+    let nn = (instr & 0x00FF) as u8;
+    let skip = |taken: bool, base: u32| if taken { base + 4 } else { base };
+    let exec = match instr >> 12 {
+        0x0 => match instr {
+            0x00E0 => 24 + 3078,
+            0x00EE => 10,
+            _ => 0,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 => skip(v[x] == nn, 10),
+        0x4 => skip(v[x] != nn, 10),
+        0x5 => skip(v[x] == v[y], 14),
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => if instr & 0xF == 0 { 12 } else { 44 },
+        0x9 => skip(v[x] != v[y], 14),
+        0xA => 12,
+        0xB => {
+            let target = (instr & 0x0FFF) + v[0] as u16;
+            if target & 0xF00 != instr & 0xF00 { 24 } else { 22 } // page crossing
+        }
+        0xC => 36,
+        0xD => {
+            // sprites not aligned to a byte need every row shifted across two bytes
+            let rows = (instr & 0xF) as u32;
+            let per_row = if v[x].is_multiple_of(8) { 46 } else { 66 };
+            26 + rows * per_row
+        }
+        0xE => {
+            let down = cpu.keypad()[(v[x] & 0xF) as usize];
+            match nn {
+                0x9E => skip(down, 14),
+                0xA1 => skip(!down, 14),
+                _ => 0,
+            }
+        }
+        0xF => match nn {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0A => 18, // one pass of the key scan loop
+            0x1E => 16,
+            0x29 => 16,
+            0x33 => {
+                // repeated subtraction, one pass per unit of each digit
+                let val = v[x] as u32;
+                80 + (val / 100 + (val / 10) % 10 + val % 10) * 16
+            }
+            0x55 | 0x65 => 14 + (x as u32 + 1) * 14,
+            _ => 0,
+        },
+        _ => 0,
+    };
+    FETCH_CYCLES + exec
+}
+
+// machine cycles for one trip round an idle loop
+fn idle_loop_cycles(idle: IdleLoop) -> u32 {
+    match idle {
+        IdleLoop::Key => FETCH_CYCLES + 18,
+        IdleLoop::Delay { .. } => 3 * FETCH_CYCLES + 10 + 10 + 12, // FX07, 3X00 not taken, 1NNN
+    }
+}
+
+pub const DEFAULT_IPF: u32 = 10; // 600 instructions per second
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Timing {
+    Fixed { ipf: u32 }, // exactly `ipf` instructions per frame
+    Vip(VipTiming),     // cycle costs and display wait of the COSMAC VIP interpreter
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::Fixed { ipf: DEFAULT_IPF }
+    }
+}
+
+impl Timing {
+    // "fixed" or "vip"
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "fixed" => Ok(Timing::default()),
+            "vip" => Ok(Timing::Vip(VipTiming::new())),
+            _ => Err(format!("Unknown timing mode: {}", s)),
+        }
+    }
+
+    // Runs one 60 Hz frame's worth of instructions, then ticks the timers once.
+    pub fn run_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        match self {
+            Timing::Fixed { ipf } => cpu.run_frame(*ipf),
+            Timing::Vip(vip) => vip.run_frame(cpu),
+        }
+    }
+}
+
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const MAX_LAG_FRAMES: u32 = 5;
+
+// Paces emulated frames to 60 per second of wall-clock time. A slow host makes
+// frames late, never shorter: when it falls too far behind, the schedule is
+// reset instead of running a burst of frames to catch up.
+#[derive(Debug)]
+pub struct FramePacer {
+    next: Instant, // when the next frame is due
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        FramePacer { next: Instant::now() + FRAME_DURATION }
+    }
+
+    // sleeps until the next frame is due
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if self.next > now {
+            std::thread::sleep(self.next - now);
+        } else if now - self.next > FRAME_DURATION * MAX_LAG_FRAMES {
+            self.next = now;
+        }
+        self.next += FRAME_DURATION;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VipTiming {
+    debt: u32, // cycles the last instruction of the previous frame ran over by
+}
+
+impl VipTiming {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cycles_available() -> u32 {
+        CYCLES_PER_FRAME - DISPLAY_DMA_CYCLES - INTERRUPT_CYCLES
+    }
+
+    // Runs one 60 Hz frame: instructions until the frame's cycle budget is
+    // spent or a DXYN waits for the vertical interrupt, then the interrupt's
+    // timer decrement.
+    pub fn run_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        let budget = Self::cycles_available();
+        let mut spent = self.debt.min(budget);
+        self.debt -= spent;
+        while spent < budget {
+            let (pc, instr) = (cpu.pc(), cpu.fetch()?);
+            spent += vip_cycles(cpu, instr);
+            cpu.execute()?;
+            if instr >> 12 == 0xD {
+                spent = budget; // display wait: nothing else runs this frame
+            } else if cpu.pc() <= pc && spent < budget {
+                if let Some(idle) = cpu.idle_loop() {
+                    let cost = idle_loop_cycles(idle);
+                    let trips = ((budget - spent) / cost) as u64;
+                    if cpu.skip_idle(trips) > 0 {
+                        spent += trips as u32 * cost;
+                    }
+                }
+            }
+        }
+        self.debt += spent - budget;
+        cpu.tick_timers();
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+// Execution tracing. Off by default; when enabled, every executed instruction
+// and/or notable event is turned into a `TraceEntry` and written to a file or
+// kept in an in-memory ring buffer.
+//
+// Text format, one entry per line:
+//
+//     c=<cycle> pc=<PC> op=<opcode> i=<I> v=<V0..VF>     instruction, state before it runs
+//     c=<cycle> <category>: <message>                    event
+//     c=<cycle> cpu: write <addr>=<byte> ...             memory written by the instruction
+//
+// `cycle` is decimal, everything else is upper-case hex; `v` is 32 hex digits,
+// two per register starting at V0.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Info,  // events only: draws, key input, timer and memory writes
+    Trace, // events plus every executed instruction
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Cpu,
+    Draw,
+    Input,
+    Timers,
+}
+
+impl Category {
+    const ALL: [Category; 4] = [Category::Cpu, Category::Draw, Category::Input, Category::Timers];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Category::Cpu => "cpu",
+            Category::Draw => "draw",
+            Category::Input => "input",
+            Category::Timers => "timers",
+        }
+    }
+
+    fn bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEntry {
+    Instr { cycle: u64, pc: u16, opcode: u16, i: u16, v: [u8; 16] },
+    Event { cycle: u64, category: Category, message: String },
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceEntry::Instr { cycle, pc, opcode, i, v } => {
+                write!(f, "c={} pc={:03X} op={:04X} i={:03X} v=", cycle, pc, opcode, i)?;
+                v.iter().try_for_each(|r| write!(f, "{:02X}", r))
+            }
+            TraceEntry::Event { cycle, category, message } => {
+                write!(f, "c={} {}: {}", cycle, category.name(), message)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Sink {
+    File(BufWriter<File>),
+    Ring { entries: VecDeque<TraceEntry>, capacity: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceConfig {
+    pub level: Level,
+    categories: u8,                             // bit set of `Category`
+    pub pc_range: Option<RangeInclusive<u16>>, // only entries from instructions in this range
+    opcode_classes: u16,                        // bit n set = opcodes nXXX are traced
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        TraceConfig {
+            level: Level::Off,
+            categories: Category::ALL.iter().fold(0, |bits, c| bits | c.bit()),
+            pc_range: None,
+            opcode_classes: 0xFFFF,
+        }
+    }
+}
+
+impl TraceConfig {
+    pub fn with_level(level: Level) -> Self {
+        TraceConfig { level, ..TraceConfig::default() }
+    }
+
+    // "off", "info" or "trace"
+    pub fn parse_level(&mut self, s: &str) -> Result<(), String> {
+        self.level = match s {
+            "off" => Level::Off,
+            "info" => Level::Info,
+            "trace" => Level::Trace,
+            _ => return Err(format!("Unknown trace level: {}", s)),
+        };
+        Ok(())
+    }
+
+    // comma separated list, e.g. "cpu,draw"
+    pub fn parse_categories(&mut self, s: &str) -> Result<(), String> {
+        self.categories = 0;
+        for name in s.split(',') {
+            let category = Category::ALL
+                .iter()
+                .find(|c| c.name() == name.trim())
+                .ok_or(format!("Unknown trace category: {}", name))?;
+            self.categories |= category.bit();
+        }
+        Ok(())
+    }
+
+    // inclusive hex range, e.g. "200-2FF"
+    pub fn parse_pc_range(&mut self, s: &str) -> Result<(), String> {
+        let (lo, hi) = s.split_once('-').ok_or(format!("Expected LO-HI address range: {}", s))?;
+        let parse = |a: &str| u16::from_str_radix(a.trim().trim_start_matches("0x"), 16).map_err(|_| format!("Bad address: {}", a));
+        self.pc_range = Some(parse(lo)?..=parse(hi)?);
+        Ok(())
+    }
+
+    // comma separated leading opcode nibbles, e.g. "D,F" for draws and FXNN
+    pub fn parse_opcode_classes(&mut self, s: &str) -> Result<(), String> {
+        self.opcode_classes = 0;
+        for class in s.split(',') {
+            let n = u8::from_str_radix(class.trim(), 16)
+                .ok()
+                .filter(|&n| n < 16)
+                .ok_or(format!("Bad opcode class: {}", class))?;
+            self.opcode_classes |= 1 << n;
+        }
+        Ok(())
+    }
+
+    fn wants(&self, level: Level, category: Category, pc: u16, opcode: u16) -> bool {
+        self.level >= level
+            && self.categories & category.bit() != 0
+            && self.opcode_classes & (1 << (opcode >> 12)) != 0
+            && self.pc_range.as_ref().is_none_or(|r| r.contains(&pc))
+    }
+}
+
+#[derive(Debug)]
+pub struct Tracer {
+    config: TraceConfig,
+    sink: Sink,
+    pc: u16,     // instruction currently executing, for filtering its events
+    opcode: u16,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Tracer::ring(TraceConfig::default(), 0)
+    }
+}
+
+impl Tracer {
+    pub fn to_file(config: TraceConfig, path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Cannot create {}: {}", path, e))?;
+        Ok(Tracer { config, sink: Sink::File(BufWriter::new(file)), pc: 0, opcode: 0 })
+    }
+
+    // keeps only the most recent `capacity` entries
+    pub fn ring(config: TraceConfig, capacity: usize) -> Self {
+        let sink = Sink::Ring { entries: VecDeque::with_capacity(capacity), capacity };
+        Tracer { config, sink, pc: 0, opcode: 0 }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.level > Level::Off
+    }
+
+    pub fn entries(&self) -> Vec<&TraceEntry> {
+        match &self.sink {
+            Sink::Ring { entries, .. } => entries.iter().collect(),
+            Sink::File(_) => Vec::new(),
+        }
+    }
+
+    pub fn instr(&mut self, cycle: u64, pc: u16, opcode: u16, i: u16, v: [u8; 16]) {
+        self.pc = pc;
+        self.opcode = opcode;
+        if self.config.wants(Level::Trace, Category::Cpu, pc, opcode) {
+            self.push(TraceEntry::Instr { cycle, pc, opcode, i, v });
+        }
+    }
+
+    pub fn event(&mut self, cycle: u64, category: Category, message: impl FnOnce() -> String) {
+        if self.config.wants(Level::Info, category, self.pc, self.opcode) {
+            self.push(TraceEntry::Event { cycle, category, message: message() });
+        }
+    }
+
+    fn push(&mut self, entry: TraceEntry) {
+        match &mut self.sink {
+            Sink::File(out) => {
+                // a full disk should not stop the emulation; the trace is best effort
+                let _ = writeln!(out, "{}", entry);
+            }
+            Sink::Ring { entries, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Sink::File(out) = &mut self.sink {
+            let _ = out.flush();
+        }
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
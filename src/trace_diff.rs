@@ -0,0 +1,178 @@
+// Compares two execution traces and reports where they first disagree.
+//
+// Both traces use the text format written by `trace::Tracer` (see trace.rs).
+// Traces from another emulator only need the subset they can produce:
+//
+//     c=<cycle> pc=<PC> op=<opcode> i=<I> v=<V0..VF>
+//     c=<cycle> cpu: write <addr>=<byte> <addr>=<byte> ...
+//
+// `c` is required on every line. Missing instruction fields are not compared,
+// and other event lines, blank lines and lines starting with `#` are ignored.
+// Steps are aligned by cycle. A cycle missing from one trace is skipped while
+// that trace goes on past it, but a trace that stops before the other does
+// diverges at the first cycle it is missing.
+
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Step {
+    pub cycle: u64,
+    pub pc: Option<u16>,
+    pub opcode: Option<u16>,
+    pub i: Option<u16>,
+    pub v: Option<[u8; 16]>,
+    pub writes: Vec<(u16, u8)>,
+    pub lines: Vec<String>, // original text, for the report
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub cycle: u64,
+    pub ours: usize,   // index into our steps
+    pub theirs: usize, // index into the reference steps
+    pub differences: Vec<String>,
+}
+
+fn hex(field: &str, value: &str, line: usize) -> Result<u16, String> {
+    u16::from_str_radix(value, 16).map_err(|_| format!("Line {}: bad {} value {}", line, field, value))
+}
+
+pub fn parse_trace(text: &str) -> Result<Vec<Step>, String> {
+    let mut steps: Vec<Step> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let n = n + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let (head, rest) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+        let cycle = head
+            .strip_prefix("c=")
+            .and_then(|c| c.parse::<u64>().ok())
+            .ok_or(format!("Line {}: expected c=<cycle>", n))?;
+        if steps.last().is_none_or(|s| s.cycle != cycle) {
+            if steps.last().is_some_and(|s| s.cycle > cycle) {
+                return Err(format!("Line {}: cycle {} goes backwards", n, cycle));
+            }
+            steps.push(Step { cycle, ..Step::default() });
+        }
+        let step = steps.last_mut().unwrap();
+        step.lines.push(line.to_string());
+
+        if let Some(writes) = rest.strip_prefix("cpu: write ") {
+            for w in writes.split_whitespace() {
+                let (addr, val) = w.split_once('=').ok_or(format!("Line {}: bad write {}", n, w))?;
+                step.writes.push((hex("address", addr, n)?, hex("byte", val, n)? as u8));
+            }
+            continue;
+        }
+        if rest.contains(": ") {
+            continue; // some other event
+        }
+        for field in rest.split_whitespace() {
+            let (key, value) = field.split_once('=').ok_or(format!("Line {}: bad field {}", n, field))?;
+            match key {
+                "pc" => step.pc = Some(hex(key, value, n)?),
+                "op" => step.opcode = Some(hex(key, value, n)?),
+                "i" => step.i = Some(hex(key, value, n)?),
+                "v" => {
+                    if value.len() != 32 {
+                        return Err(format!("Line {}: v needs 32 hex digits", n));
+                    }
+                    let mut v = [0u8; 16];
+                    for (r, reg) in v.iter_mut().enumerate() {
+                        *reg = hex(key, &value[r * 2..r * 2 + 2], n)? as u8;
+                    }
+                    step.v = Some(v);
+                }
+                _ => {} // fields we don't know are allowed and ignored
+            }
+        }
+    }
+    Ok(steps)
+}
+
+fn compare(ours: &Step, theirs: &Step) -> Vec<String> {
+    let mut diffs = Vec::new();
+    let mut field = |name: &str, a: Option<u16>, b: Option<u16>, width: usize| {
+        if let (Some(a), Some(b)) = (a, b) {
+            if a != b {
+                diffs.push(format!("{}: ours {:0w$X}, reference {:0w$X}", name, a, b, w = width));
+            }
+        }
+    };
+    field("PC", ours.pc, theirs.pc, 3);
+    field("opcode", ours.opcode, theirs.opcode, 4);
+    field("I", ours.i, theirs.i, 3);
+    if let (Some(a), Some(b)) = (ours.v, theirs.v) {
+        for r in 0..16 {
+            if a[r] != b[r] {
+                diffs.push(format!("V{:X}: ours {:02X}, reference {:02X}", r, a[r], b[r]));
+            }
+        }
+    }
+    let a: BTreeSet<_> = ours.writes.iter().collect();
+    let b: BTreeSet<_> = theirs.writes.iter().collect();
+    for (addr, val) in a.difference(&b) {
+        diffs.push(format!("write {:03X}={:02X} only in ours", addr, val));
+    }
+    for (addr, val) in b.difference(&a) {
+        diffs.push(format!("write {:03X}={:02X} only in reference", addr, val));
+    }
+    diffs
+}
+
+pub fn first_divergence(ours: &[Step], theirs: &[Step]) -> Option<Divergence> {
+    let (mut a, mut b) = (0, 0);
+    while a < ours.len() && b < theirs.len() {
+        match ours[a].cycle.cmp(&theirs[b].cycle) {
+            std::cmp::Ordering::Less => a += 1,
+            std::cmp::Ordering::Greater => b += 1,
+            std::cmp::Ordering::Equal => {
+                let differences = compare(&ours[a], &theirs[b]);
+                if !differences.is_empty() {
+                    return Some(Divergence { cycle: ours[a].cycle, ours: a, theirs: b, differences });
+                }
+                a += 1;
+                b += 1;
+            }
+        }
+    }
+    let ended = |steps: &[Step]| match steps.last() {
+        Some(last) => format!("ends after cycle {}", last.cycle),
+        None => "is empty".to_string(),
+    };
+    if a < ours.len() {
+        let differences = vec![format!("reference {}", ended(theirs))];
+        return Some(Divergence { cycle: ours[a].cycle, ours: a, theirs: b, differences });
+    }
+    if b < theirs.len() {
+        let differences = vec![format!("ours {}", ended(ours))];
+        return Some(Divergence { cycle: theirs[b].cycle, ours: a, theirs: b, differences });
+    }
+    None
+}
+
+fn context(out: &mut String, title: &str, steps: &[Step], at: usize, lines: usize) {
+    out.push_str(&format!("{}:\n", title));
+    let start = at.saturating_sub(lines);
+    let end = (at + lines + 1).min(steps.len());
+    for (n, step) in steps.iter().enumerate().take(end).skip(start) {
+        for line in &step.lines {
+            out.push_str(if n == at { "> " } else { "  " });
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+}
+
+// human-readable description of `div`, with `lines` steps of context either side
+pub fn report(ours: &[Step], theirs: &[Step], div: &Divergence, lines: usize) -> String {
+    let mut out = format!("first divergence at cycle {}:\n", div.cycle);
+    for d in &div.differences {
+        out.push_str(&format!("  {}\n", d));
+    }
+    context(&mut out, "ours", ours, div.ours, lines);
+    context(&mut out, "reference", theirs, div.theirs, lines);
+    out
+}
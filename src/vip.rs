@@ -0,0 +1,296 @@
+// Whole-system COSMAC VIP: a CDP1802, the CDP1861 video chip, the hex keypad
+// and 2-4 KiB of RAM, running the original CHIP-8 interpreter from a dump.
+//
+// The interpreter goes at 0x000 and the CHIP-8 program at 0x200, and the 1802
+// starts from reset, as it would after loading both from tape and flipping
+// the VIP to RUN. A dump of the monitor ROM can be mapped at 0x8000 too;
+// the interpreter takes its hex digit sprites from it.
+//
+// The 1861 draws 262 lines of 14 machine cycles per 60 Hz frame. While it is
+// on (INP 1 turns it on, OUT 1 off) it interrupts the 1802 at line 78 and
+// then DMAs 8 bytes from R0 at the start of each of the 128 display lines.
+// EF1 is asserted for the 4 lines before the display starts and before it
+// ends. The interpreter's interrupt routine points R0 at the display page,
+// repeats each row for 4 lines and counts down the timers. The keypad is
+// OUT 2 to select a key and EF3 to see whether it is down; Q drives the tone.
+//
+// `check` runs a ROM on this and on `CPU` side by side, one CHIP-8
+// instruction at a time, to validate the high-level interpreter against it.
+
+use crate::cdp1802::{self, Cdp1802};
+use crate::cpu::{Quirks, StackConfig, CPU, HEIGHT, WIDTH};
+use crate::framebuffer::Framebuffer;
+
+const ROM_START: u16 = 0x8000;
+const LINES: u32 = 262;
+const CYCLES_PER_LINE: u32 = 14;
+const INTERRUPT_LINE: u32 = 78;
+const DISPLAY_START: u32 = 80;
+pub const VIDEO_LINES: usize = 128;
+const INTERPRETER_SIZE: usize = 0x200;
+const MAX_INSTRUCTION_CYCLES: u64 = 60 * 3668; // a second of machine time for one CHIP-8 instruction
+
+// what the 1802 sees
+struct VipBus {
+    ram: Vec<u8>,     // mirrored up to 0x8000
+    monitor: Vec<u8>, // mirrored from 0x8000 up; empty reads as 0
+    keypad: [bool; 16],
+    key: u8, // selected by OUT 2
+    display_on: bool,
+    line: u32,
+}
+
+impl cdp1802::Bus for VipBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr < ROM_START {
+            self.ram[addr as usize % self.ram.len()]
+        } else if self.monitor.is_empty() {
+            0
+        } else {
+            self.monitor[(addr - ROM_START) as usize % self.monitor.len()]
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        if addr < ROM_START {
+            let len = self.ram.len();
+            self.ram[addr as usize % len] = val;
+        }
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        match port {
+            1 => self.display_on = false,
+            2 => self.key = val & 0xF,
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display_on = true;
+        }
+        0
+    }
+
+    fn ef(&mut self, line: u8) -> bool {
+        match line {
+            1 => {
+                let end = DISPLAY_START + VIDEO_LINES as u32;
+                self.display_on && (self.line + 4 >= DISPLAY_START && self.line < DISPLAY_START || self.line + 4 >= end && self.line < end)
+            }
+            3 => self.keypad[self.key as usize],
+            _ => false,
+        }
+    }
+}
+
+pub struct Vip {
+    core: Cdp1802,
+    bus: VipBus,
+    line_cycles: u32, // machine cycles into the current line
+    frames: u64,
+    video: [u64; VIDEO_LINES], // the picture, one bit per pixel, as of the last DMA of each line
+}
+
+impl Vip {
+    // `ram` is the RAM size in bytes, from 2048 to 4096
+    pub fn new(interpreter: &[u8], monitor: Option<&[u8]>, ram: usize, rom: &[u8]) -> Result<Self, String> {
+        if !(2048..=4096).contains(&ram) || !ram.is_multiple_of(1024) {
+            return Err(format!("VIP RAM must be 2, 3 or 4 KiB: {} bytes", ram));
+        }
+        if interpreter.len() > INTERPRETER_SIZE {
+            return Err(format!("Interpreter is too large: {} bytes", interpreter.len()));
+        }
+        if rom.len() > ram - INTERPRETER_SIZE {
+            return Err(format!("ROM is too large: {} bytes", rom.len()));
+        }
+        let mut mem = vec![0; ram];
+        mem[..interpreter.len()].copy_from_slice(interpreter);
+        mem[INTERPRETER_SIZE..INTERPRETER_SIZE + rom.len()].copy_from_slice(rom);
+        let bus = VipBus { ram: mem, monitor: monitor.unwrap_or_default().to_vec(), keypad: [false; 16], key: 0, display_on: false, line: 0 };
+        Ok(Vip { core: Cdp1802::new(), bus, line_cycles: 0, frames: 0, video: [0; VIDEO_LINES] })
+    }
+
+    pub fn set_keypad(&mut self, keypad: [bool; 16]) {
+        self.bus.keypad = keypad;
+    }
+
+    // one 1802 instruction, then whatever the 1861 does in the time it took
+    pub fn step(&mut self) -> Result<(), String> {
+        let cycles = self.core.step(&mut self.bus)?;
+        self.advance(cycles);
+        Ok(())
+    }
+
+    fn advance(&mut self, cycles: u32) {
+        self.line_cycles += cycles;
+        while self.line_cycles >= CYCLES_PER_LINE {
+            self.line_cycles -= CYCLES_PER_LINE;
+            self.bus.line = (self.bus.line + 1) % LINES;
+            if self.bus.line == 0 {
+                self.frames += 1;
+            }
+            self.start_line();
+        }
+    }
+
+    fn start_line(&mut self) {
+        if !self.bus.display_on {
+            return;
+        }
+        let line = self.bus.line;
+        if line == INTERRUPT_LINE {
+            self.core.interrupt();
+        }
+        if (DISPLAY_START..DISPLAY_START + VIDEO_LINES as u32).contains(&line) {
+            let mut row = 0u64;
+            for _ in 0..8 {
+                row = row << 8 | self.core.dma_out(&mut self.bus) as u64;
+            }
+            self.video[(line - DISPLAY_START) as usize] = row;
+            self.line_cycles += 8; // taken from the 1802
+        }
+    }
+
+    // runs until the 1861 starts its next frame
+    pub fn run_frame(&mut self) -> Result<(), String> {
+        let frame = self.frames;
+        while self.frames == frame {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // Runs until the interpreter finishes a CHIP-8 instruction, which is when
+    // its routine for it hands back to the main loop with D4 (SEP R4).
+    pub fn step_instruction(&mut self) -> Result<(), String> {
+        let start = self.core.cycles;
+        loop {
+            let pc = self.core.r[self.core.p as usize];
+            let returning = self.core.p != 4 && !self.core.idle && cdp1802::Bus::read(&mut self.bus, pc) == 0xD4;
+            self.step()?;
+            if returning && self.core.p == 4 {
+                return Ok(());
+            }
+            if self.core.cycles - start > MAX_INSTRUCTION_CYCLES {
+                return Err(format!("Interpreter did not finish the instruction at 0x{:03X}", self.chip8_pc()));
+            }
+        }
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn core(&self) -> &Cdp1802 {
+        &self.core
+    }
+
+    pub fn tone(&self) -> bool {
+        self.core.q
+    }
+
+    // the picture the 1861 last sent, 64 pixels by 128 lines
+    pub fn video(&self) -> &[u64; VIDEO_LINES] {
+        &self.video
+    }
+
+    // The picture at CHIP-8 resolution: the interpreter shows each row for
+    // four lines, so this takes the first of each four.
+    pub fn display(&self) -> Framebuffer {
+        let mut display = Framebuffer::new(HEIGHT);
+        for y in 0..HEIGHT {
+            display.set_row(y, self.video[y * VIDEO_LINES / HEIGHT]);
+        }
+        display
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.bus.ram
+    }
+
+    // The CHIP-8 machine as the interpreter keeps it: PC in R5, I in RA, V0-VF
+    // just below the work area at the top of RAM and the display in the last page.
+    pub fn chip8_pc(&self) -> usize {
+        self.core.r[5] as usize
+    }
+
+    pub fn chip8_index(&self) -> u16 {
+        self.core.r[0xA]
+    }
+
+    pub fn chip8_registers(&self) -> [u8; 16] {
+        let at = self.bus.ram.len() - 0x110;
+        self.bus.ram[at..at + 16].try_into().unwrap()
+    }
+
+    // the display page in RAM, whether or not the 1861 is showing it
+    pub fn chip8_display(&self) -> Framebuffer {
+        let page = &self.bus.ram[self.bus.ram.len() - 0x100..];
+        let mut display = Framebuffer::new(HEIGHT);
+        for (y, row) in page.chunks(WIDTH / 8).enumerate() {
+            display.set_row(y, u64::from_be_bytes(row.try_into().unwrap()));
+        }
+        display
+    }
+}
+
+// `CPU` set up to behave as the VIP interpreter does
+pub fn reference_cpu(rom: &[u8]) -> Result<CPU, String> {
+    let mut cpu = CPU::new();
+    cpu.set_quirks(Quirks { vf_reset: true, increment_i: true, shift_vx: false, jump_vx: false, wrap_sprites: false });
+    cpu.set_stack_config(StackConfig::VIP)?;
+    cpu.set_memory_display(true);
+    cpu.set_hybrid(true);
+    cpu.load_bytes(rom)?;
+    Ok(cpu)
+}
+
+// Runs `vip` up to its first instruction at 0x200 (the interpreter runs a
+// couple of its own before), then `instructions` more on it and on `cpu`
+// side by side. Returns where they first disagreed about PC, V0-VF, I or the
+// display, if they did. I is only compared while the VIP's points into RAM,
+// since the two keep their hex digit sprites in different places. CXNN and
+// timers read at different moments within a frame will show up here too.
+pub fn check(vip: &mut Vip, cpu: &mut CPU, instructions: u64) -> Result<Option<String>, String> {
+    for _ in 0..64 {
+        if vip.chip8_pc() == 0x200 && vip.core.p == 4 {
+            break;
+        }
+        vip.step_instruction()?;
+    }
+    if vip.chip8_pc() != 0x200 {
+        return Err(format!("Interpreter never reached 0x200 (R5 = 0x{:04X})", vip.chip8_pc()));
+    }
+    for n in 0..instructions {
+        let (pc, instr) = (cpu.pc(), cpu.fetch()?);
+        let frames = vip.frames();
+        vip.step_instruction()?;
+        for _ in frames..vip.frames() {
+            cpu.tick_timers();
+        }
+        cpu.execute()?;
+
+        let differs = |what: &str, vip: String, ours: String| {
+            Some(format!("after {} instructions, {:04X} at 0x{:03X}: {} is {} on the VIP but {} here", n + 1, instr, pc, what, vip, ours))
+        };
+        let report = if vip.chip8_pc() != cpu.pc() {
+            differs("PC", format!("0x{:03X}", vip.chip8_pc()), format!("0x{:03X}", cpu.pc()))
+        } else if vip.chip8_registers() != *cpu.registers() {
+            let v = vip.chip8_registers();
+            let x = (0..16).find(|&x| v[x] != cpu.registers()[x]).unwrap();
+            differs(&format!("V{:X}", x), format!("0x{:02X}", v[x]), format!("0x{:02X}", cpu.registers()[x]))
+        } else if (vip.chip8_index() as usize) < vip.ram().len() && vip.chip8_index() as u32 != cpu.index() {
+            differs("I", format!("0x{:03X}", vip.chip8_index()), format!("0x{:03X}", cpu.index()))
+        } else if let Some(y) = (0..HEIGHT).find(|&y| vip.chip8_display().rows()[y] != cpu.display().rows()[y]) {
+            differs(&format!("display row {}", y), format!("{:016X}", vip.chip8_display().rows()[y]), format!("{:016X}", cpu.display().rows()[y]))
+        } else {
+            None
+        };
+        if report.is_some() {
+            return Ok(report);
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,68 @@
+use minifb::{Key, Window, WindowOptions};
+use crate::cpu::CPU;
+use crate::keymap::KeyMap;
+use crate::phosphor::{DisplayFilter, DisplayMode};
+use crate::timing::{FramePacer, Timing};
+
+// the character a key types without shift, for looking it up in a `KeyMap`
+fn key_char(key: Key) -> Option<char> {
+    let c = match key {
+        Key::Key0 => '0', Key::Key1 => '1', Key::Key2 => '2', Key::Key3 => '3', Key::Key4 => '4',
+        Key::Key5 => '5', Key::Key6 => '6', Key::Key7 => '7', Key::Key8 => '8', Key::Key9 => '9',
+        Key::A => 'a', Key::B => 'b', Key::C => 'c', Key::D => 'd', Key::E => 'e', Key::F => 'f',
+        Key::G => 'g', Key::H => 'h', Key::I => 'i', Key::J => 'j', Key::K => 'k', Key::L => 'l',
+        Key::M => 'm', Key::N => 'n', Key::O => 'o', Key::P => 'p', Key::Q => 'q', Key::R => 'r',
+        Key::S => 's', Key::T => 't', Key::U => 'u', Key::V => 'v', Key::W => 'w', Key::X => 'x',
+        Key::Y => 'y', Key::Z => 'z',
+        Key::Apostrophe => '\'', Key::Backquote => '`', Key::Backslash => '\\', Key::Comma => ',',
+        Key::Equal => '=', Key::LeftBracket => '[', Key::Minus => '-', Key::Period => '.',
+        Key::RightBracket => ']', Key::Semicolon => ';', Key::Slash => '/', Key::Space => ' ',
+        _ => return None,
+    };
+    Some(c)
+}
+
+// minifb frontend: owns the window and drives `cpu` in real time until it is closed
+pub fn start(cpu: &mut CPU, display_mode: DisplayMode, keys: &KeyMap, mut timing: Timing) -> Result<(), String> {
+    let (width, height) = cpu.output_size(); // fixed once the ROM is loaded
+    let mut window = Window::new(
+        "Minifb Test Window",
+        width,
+        height,
+        WindowOptions {
+            scale: match width { // about 1024 pixels wide whatever the size
+                64 if height > 32 => minifb::Scale::X8,
+                64 => minifb::Scale::X16,
+                _ => minifb::Scale::X4,
+            },
+            ..WindowOptions::default()
+        },
+    ).map_err(|e| format!("Failed to create window: {}", e))?;
+    window.limit_update_rate(None); // the frame pacer below does the waiting
+
+    let mut display_filter = DisplayFilter::new(display_mode); // anti-flicker post-processing, never touches the CPU display
+    let mut buffer = vec![0u32; width * height]; // raw display; only changed rows are updated
+    let mut frame = buffer.clone(); // what is shown, after the filter
+    let mut pacer = FramePacer::new();
+
+    // one iteration per 60 Hz frame: input, a frame of instructions and timers, present
+    while window.is_open() {
+        // === Process Input: minifb refreshes key state on each window update ===
+        let mut keypads = [[false; 16]; 2];
+        for (pad, chip8_key) in window.get_keys().into_iter().filter_map(key_char).filter_map(|c| keys.lookup(c)) {
+            keypads[pad][chip8_key as usize] = true;
+        }
+        cpu.set_keypad(keypads[0]);
+        cpu.set_keypad2(keypads[1]);
+
+        timing.run_frame(cpu)?; // next to nothing when the ROM idles, leaving the pacer to sleep
+
+        cpu.update_display_buffer(&mut buffer);
+        frame.copy_from_slice(&buffer);
+        display_filter.apply(&mut frame);
+        window.update_with_buffer(&frame, width, height).unwrap();
+
+        pacer.wait();
+    }
+    Ok(())
+}
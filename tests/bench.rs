@@ -0,0 +1,47 @@
+use chip8_emulator::bench::{self, BenchConfig, Limit};
+
+mod common;
+use common::rom;
+
+#[test]
+fn counts_instructions_frames_and_classes() {
+    // 6000, then a loop of 7001, D005 and 1202
+    let rom = rom(&[0x6000, 0x7001, 0xD005, 0x1202]);
+    let config = BenchConfig { limit: Limit::Instructions(1000), ipf: 7, jit: false };
+    let report = bench::run(&rom, &config).unwrap();
+    assert_eq!(report.instructions, 1000);
+    assert_eq!(report.frames, 143); // 142 full frames and one of 6
+    let counts: Vec<u64> = report.classes.iter().map(|c| c.count).collect();
+    assert_eq!(counts.iter().sum::<u64>(), 1000);
+    assert_eq!(counts[0x6], 1);
+    assert_eq!((counts[0x7], counts[0xD], counts[0x1]), (333, 333, 333));
+    let text = report.to_string();
+    assert!(text.contains("1000 instructions, 143 frames"), "{}", text);
+    assert!(text.contains("  Dxxx           333"), "{}", text);
+    assert!(!text.contains("Axxx"), "{}", text);
+}
+
+#[test]
+fn compiled_blocks_run_the_same_instructions() {
+    let rom = rom(&[0x6000, 0x7001, 0xD005, 0x1202]);
+    let config = BenchConfig { limit: Limit::Instructions(500), ipf: 10, jit: true };
+    let report = bench::run(&rom, &config).unwrap();
+    assert_eq!((report.instructions, report.frames), (500, 50));
+    assert!(report.to_string().contains("compiled blocks"));
+}
+
+#[test]
+fn time_limit_stops_the_run() {
+    let rom = rom(&[0x7001, 0x1200]);
+    let config = BenchConfig { limit: Limit::Seconds(0.05), ..BenchConfig::default() };
+    let report = bench::run(&rom, &config).unwrap();
+    assert!(report.elapsed.as_secs_f64() >= 0.05);
+    assert!(report.frames > 0);
+    assert_eq!(report.instructions, report.frames * config.ipf as u64);
+}
+
+#[test]
+fn errors_are_reported() {
+    let config = BenchConfig { limit: Limit::Instructions(10), ..BenchConfig::default() };
+    assert!(bench::run(&rom(&[0x0123]), &config).is_err());
+}
@@ -0,0 +1,133 @@
+use chip8_emulator::cdp1802::{Bus, Cdp1802};
+use chip8_emulator::cpu::CPU;
+
+mod common;
+
+struct Ram(Vec<u8>);
+
+impl Bus for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.0[addr as usize] = val;
+    }
+}
+
+// runs `program` from 0 with R0 as PC until it reaches `end`
+fn run_1802(program: &[u8], end: u16) -> (Cdp1802, Ram) {
+    let mut ram = Ram(vec![0; 0x100]);
+    ram.0[..program.len()].copy_from_slice(program);
+    let mut core = Cdp1802::new();
+    for _ in 0..1000 {
+        if core.r[core.p as usize] == end {
+            return (core, ram);
+        }
+        core.step(&mut ram).unwrap();
+    }
+    panic!("never got to 0x{:02X}", end);
+}
+
+#[test]
+fn arithmetic_sets_df() {
+    // 0x80 + 0x90 = 0x110: D = 0x10, DF = 1
+    let (core, _) = run_1802(&[0xF8, 0x80, 0xFC, 0x90], 4);
+    assert_eq!((core.d, core.df), (0x10, true));
+    // 0x10 - 0x20 borrows: DF = 0
+    let (core, _) = run_1802(&[0xF8, 0x10, 0xFF, 0x20], 4);
+    assert_eq!((core.d, core.df), (0xF0, false));
+    // SDI: 0x30 - 0x10 with no borrow, then SHRC pulls DF into bit 7
+    let (core, _) = run_1802(&[0xF8, 0x10, 0xFD, 0x30, 0x76], 5);
+    assert_eq!((core.d, core.df), (0x90, false));
+}
+
+#[test]
+fn branches_and_subroutines() {
+    let program = [
+        0xF8, 0x00, // 00: LDI 0
+        0x32, 0x06, // 02: BZ 06
+        0xF8, 0xFF, // 04: (skipped)
+        0xC2, 0x00, 0x0C, // 06: LBZ 000C
+        0x00, 0x00, 0x00, // 09
+        0xF8, 0x20, 0xA2, // 0C: R2 = 0x20
+        0xE2, 0x79, // 0F: SEX 2, MARK: T = 0x20 (X=2, P=0), M(R2) = T, X = P, R2 = 0x1F
+        0xE2, 0x12, 0x70, // 11: SEX 2, INC R2, RET: X, P = 2, 0 again, R2 = 0x21
+    ];
+    let (core, ram) = run_1802(&program, 0x14);
+    assert_eq!(core.d, 0x20);
+    assert_eq!(ram.0[0x20], 0x20);
+    assert_eq!((core.x, core.p, core.r[2]), (2, 0, 0x21));
+}
+
+#[test]
+fn extended_opcodes_are_refused() {
+    let mut core = Cdp1802::new();
+    assert!(core.step(&mut Ram(vec![0x68, 0x00])).is_err());
+}
+
+fn hybrid(words: &[u16], machine_code: &[u8]) -> CPU {
+    let mut rom = common::rom(words);
+    rom.resize(0x100, 0);
+    rom.extend_from_slice(machine_code); // at 0x300
+    let mut cpu = CPU::new();
+    cpu.set_hybrid(true);
+    cpu.load_bytes(&rom).unwrap();
+    cpu
+}
+
+#[test]
+fn machine_code_sets_vx_through_r6() {
+    // LDI 42, STR R6, SEP R4; X of 0300 is 3
+    let mut cpu = hybrid(&[0x6301, 0x0300, 0x7301], &[0xF8, 0x42, 0x56, 0xD4]);
+    cpu.run(3).unwrap();
+    assert_eq!(cpu.registers()[3], 0x43);
+    assert_eq!(cpu.pc(), 0x206);
+}
+
+#[test]
+fn machine_code_sees_i_the_display_and_the_chip8_pc() {
+    // copy M(I) into the display's first byte, then step R5 past the next CHIP-8 instruction
+    let code = [0x4A, 0x5B, 0x15, 0x15, 0xD4]; // LDA RA, STR RB, INC R5, INC R5, SEP R4
+    let mut cpu = hybrid(&[0xA20A, 0x0300, 0x6001, 0x6102, 0x1208, 0xFF00], &code);
+    cpu.run(4).unwrap();
+    assert_eq!(cpu.registers()[..2], [0, 2]);
+    assert_eq!(cpu.index(), 0x20B);
+    let lit: Vec<usize> = (0..64).filter(|&x| cpu.display().pixel(x, 0)).collect();
+    assert_eq!(lit, (0..8).collect::<Vec<_>>());
+}
+
+#[test]
+fn machine_code_reads_the_keypad() {
+    let code = [
+        0xE6, // SEX 6
+        0x62, // OUT 2: select the key in V3
+        0x26, // DEC 6
+        0xF8, 0x01, // LDI 1
+        0x36, 0x09, // B3: key down
+        0xF8, 0x00, // LDI 0
+        0x56, // STR R6
+        0xD4,
+    ];
+    for (down, jit) in [(true, false), (false, false), (true, true)] {
+        let mut cpu = hybrid(&[0x6305, 0x0300, 0x1204], &code);
+        cpu.set_jit(jit);
+        let mut keypad = [false; 16];
+        keypad[5] = down;
+        cpu.set_keypad(keypad);
+        cpu.run(2).unwrap();
+        assert_eq!(cpu.registers()[3], down as u8);
+    }
+}
+
+#[test]
+fn machine_calls_fail_without_hybrid_or_return() {
+    let mut cpu = hybrid(&[0x0300], &[0x30, 0x00]); // BR 00: spins forever
+    let err = cpu.run(1).unwrap_err();
+    assert!(err.contains("did not return"), "{}", err);
+
+    let mut cpu = hybrid(&[0x0300], &[0xD4]);
+    cpu.set_hybrid(false);
+    let err = cpu.run(1).unwrap_err();
+    assert!(err.contains("0x0300"), "{}", err);
+}
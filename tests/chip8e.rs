@@ -0,0 +1,100 @@
+use chip8_emulator::cpu::{Platform, CPU};
+
+mod common;
+use common::rom;
+
+fn load(words: &[u16]) -> CPU {
+    common::load_with(words, |cpu| cpu.set_platform(Platform::Chip8E))
+}
+
+// every CHIP-8E instruction that does not wait, ending on 00ED at 0x22E
+const ROM: [u16; 24] = [
+    0x6005, 0x6103, 0x5011, 0x00ED, // 200: V0 > V1, so skip the stop
+    0x5101, 0x0188, 0x00ED, 0xA300, // 208: V1 > V0 does not skip; 0188 always does
+    0x5012, 0x6200, 0xA300, 0x5123, // 210: V0-V1 to 0x300, back into V1-V2
+    0xBF02, 0x00ED, 0x6304, 0xF31B, // 218: forward over a stop; skip V3 = 4 bytes
+    0x00ED, 0x00ED, 0x7401, 0x3403, // 220: count V4 up to 3...
+    0xBB06, 0xF003, 0x00F2, 0x00ED, // 228: ...looping back; V0 out; no-op; stop
+];
+
+#[test]
+fn extended_instructions() {
+    for jit in [false, true] {
+        let mut cpu = load(&ROM);
+        cpu.set_jit(jit);
+        cpu.run(30).unwrap();
+        assert_eq!(cpu.pc(), 0x22E, "jit {}", jit);
+        assert_eq!(cpu.registers()[..5], [5, 5, 3, 4, 3]);
+        assert_eq!(cpu.index(), 0x302);
+        assert_eq!([cpu.read_mem(0x300), cpu.read_mem(0x301)], [5, 3]);
+        assert_eq!(cpu.output(), 5);
+    }
+}
+
+#[test]
+fn delay_waits() {
+    // FX4F with 3, then 0151 after FX15 with 2
+    let rom = [0x6003, 0xF04F, 0x6202, 0xF215, 0x0151, 0x6101];
+    for jit in [false, true] {
+        let mut cpu = load(&rom);
+        cpu.set_jit(jit);
+        cpu.run(10).unwrap();
+        assert_eq!(cpu.pc(), 0x202);
+        for _ in 0..2 {
+            cpu.tick_timers();
+            cpu.run(5).unwrap(); // the timer is only set once
+            assert_eq!(cpu.pc(), 0x202);
+        }
+        cpu.tick_timers();
+        cpu.run(6).unwrap();
+        assert_eq!(cpu.pc(), 0x208);
+        cpu.tick_timers();
+        cpu.run(3).unwrap();
+        assert_eq!(cpu.pc(), 0x208);
+        cpu.tick_timers();
+        cpu.run(2).unwrap();
+        assert_eq!(cpu.registers()[1], 1);
+    }
+}
+
+#[test]
+fn waiting_survives_save_states() {
+    let mut cpu = load(&[0x6002, 0xF04F, 0x6101]);
+    cpu.run(3).unwrap();
+    cpu.tick_timers();
+    let state = cpu.save_state().unwrap();
+    let mut restored = load(&[0x6002, 0xF04F, 0x6101]);
+    restored.load_state(&state).unwrap();
+    restored.tick_timers();
+    restored.run(2).unwrap();
+    assert_eq!(restored.registers()[1], 1);
+}
+
+#[test]
+fn input_port() {
+    let mut cpu = load(&[0xF0E3, 0xF1E7, 0x1204]);
+    cpu.run(5).unwrap();
+    assert_eq!(cpu.pc(), 0x200);
+    cpu.set_input(0x42);
+    cpu.run(1).unwrap();
+    assert_eq!(cpu.registers()[0], 0x42);
+    cpu.run(1).unwrap();
+    assert_eq!(cpu.registers()[1], 0x42);
+    let mut cpu = load(&[0xF1E7, 0xF0E3]);
+    cpu.run(3).unwrap();
+    assert_eq!(cpu.registers()[1], 0); // nothing there yet, but no waiting either
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn plain_chip8_is_unchanged() {
+    // 5011 is 5XY0 and BF02 jumps to 0xF02 + V0
+    let rom: Vec<u8> = rom(&[0x6001, 0x5011, 0x00E0, 0xBF02]);
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom).unwrap();
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.pc(), 0x204);
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.pc(), 0xF03);
+    assert_eq!(Platform::parse("chip8e").unwrap(), Platform::Chip8E);
+}
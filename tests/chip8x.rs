@@ -0,0 +1,111 @@
+use chip8_emulator::chip8x::{BACKGROUNDS, FOREGROUNDS};
+use chip8_emulator::cpu::{Platform, CPU, HEIGHT, WIDTH};
+
+mod common;
+use common::rom;
+
+fn load(words: &[u16]) -> CPU {
+    common::load_with(words, |cpu| cpu.set_platform(Platform::Chip8X))
+}
+
+fn picture(cpu: &mut CPU) -> Vec<u32> {
+    let mut buffer = vec![0; WIDTH * HEIGHT];
+    cpu.update_display_buffer(&mut buffer);
+    buffer
+}
+
+#[test]
+fn programs_start_at_0x300() {
+    let mut cpu = load(&[0x6042, 0x1302]);
+    assert_eq!(cpu.pc(), 0x300);
+    cpu.run(3).unwrap();
+    assert_eq!(cpu.registers()[0], 0x42);
+    assert_eq!(cpu.pc(), 0x302);
+}
+
+#[test]
+fn nibbles_add_modulo_8() {
+    let mut cpu = load(&[0x6035, 0x6116, 0x5011]);
+    cpu.run(3).unwrap();
+    assert_eq!(cpu.registers()[0], 0x43);
+    assert_eq!(cpu.registers()[1], 0x16);
+}
+
+#[test]
+fn zones_colour_lit_pixels() {
+    // an 8 pixel bar at 0,4, then zone column 2 and the one after it in
+    // zone row 1 (lines 4-7) turned green (4), and the background cycled once
+    let rom = [
+        0xA312, 0x6000, 0x6104, 0xD011, // 300
+        0x6012, 0x6101, 0x6204, 0xB020, // 308
+        0x02A0, // 310
+        0xFF00, // 312: sprite
+    ];
+    let mut cpu = load(&rom);
+    cpu.run(5).unwrap();
+    let before = picture(&mut cpu);
+    assert_eq!(before[4 * WIDTH], FOREGROUNDS[1]); // red until told otherwise
+    assert_eq!(before[4 * WIDTH + 8], BACKGROUNDS[0]);
+    assert_eq!(before[0], BACKGROUNDS[0]);
+
+    cpu.run(4).unwrap();
+    let after = picture(&mut cpu);
+    let line: Vec<u32> = (0..24).step_by(8).map(|x| after[4 * WIDTH + x]).collect();
+    assert_eq!(line, [FOREGROUNDS[1], BACKGROUNDS[1], BACKGROUNDS[1]]);
+    assert_eq!(cpu.colours().foreground(16, 7), FOREGROUNDS[4]);
+    assert_eq!(cpu.colours().foreground(31, 4), FOREGROUNDS[4]);
+    assert_eq!(cpu.colours().foreground(32, 4), FOREGROUNDS[1]);
+    assert_eq!(cpu.colours().foreground(16, 8), FOREGROUNDS[1]);
+    assert_eq!(after[0], BACKGROUNDS[1]);
+
+    // colours are part of the saved state
+    let state = cpu.save_state().unwrap();
+    let mut restored = load(&rom);
+    restored.load_state(&state).unwrap();
+    assert_eq!(picture(&mut restored), after);
+}
+
+#[test]
+fn sprite_colour_covers_the_sprite() {
+    // white (7) for an 8x3 sprite at 12,30: columns 1 and 2, lines 30, 31 and 0
+    let mut cpu = load(&[0x600C, 0x611E, 0x6207, 0xB023]);
+    cpu.run(4).unwrap();
+    let colours = cpu.colours();
+    for (x, y) in [(8, 30), (23, 31), (16, 0)] {
+        assert_eq!(colours.foreground(x, y), FOREGROUNDS[7], "{} {}", x, y);
+    }
+    for (x, y) in [(7, 30), (24, 30), (8, 1), (8, 29)] {
+        assert_eq!(colours.foreground(x, y), FOREGROUNDS[1], "{} {}", x, y);
+    }
+}
+
+#[test]
+fn second_keypad_skips() {
+    let rom = [0x6305, 0xE3F2, 0x1300, 0xE3F5, 0x1306, 0x6001, 0x1300];
+    for jit in [false, true] {
+        let mut cpu = load(&rom);
+        cpu.set_jit(jit);
+        let mut keypad = [false; 16];
+        keypad[5] = true;
+        cpu.set_keypad(keypad); // the first keypad does not count
+        cpu.run(3).unwrap();
+        assert_eq!(cpu.pc(), 0x300);
+        cpu.set_keypad2(keypad);
+        cpu.run(3).unwrap();
+        assert_eq!(cpu.pc(), 0x308);
+        cpu.set_keypad2([false; 16]);
+        cpu.run(3).unwrap();
+        assert_eq!(cpu.registers()[0], 1);
+    }
+}
+
+#[test]
+fn plain_chip8_is_unchanged() {
+    let rom: Vec<u8> = rom(&[0x6002, 0xB300]);
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom).unwrap();
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.pc(), 0x302);
+    assert_eq!(Platform::parse("chip8x").unwrap(), Platform::Chip8X);
+    assert!(Platform::parse("chip9").is_err());
+}
@@ -0,0 +1,23 @@
+// Helpers shared by the integration tests. Each test file is its own crate
+// and uses only some of them.
+#![allow(dead_code)]
+
+use chip8_emulator::cpu::CPU;
+
+// a ROM image from instruction words, high byte first
+pub fn rom(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+// a fresh CPU with `words` loaded at the start address
+pub fn load(words: &[u16]) -> CPU {
+    load_with(words, |_| {})
+}
+
+// the same, with `setup` run on the CPU before the ROM goes in
+pub fn load_with(words: &[u16], setup: impl FnOnce(&mut CPU)) -> CPU {
+    let mut cpu = CPU::new();
+    setup(&mut cpu);
+    cpu.load_bytes(&rom(words)).unwrap();
+    cpu
+}
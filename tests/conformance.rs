@@ -0,0 +1,154 @@
+// Headless conformance tests. Each case runs a ROM for a fixed number of
+// frames and compares the final screen with `tests/golden/<name>.txt`.
+//
+// The Timendus test suite ROMs (https://github.com/Timendus/chip8-test-suite)
+// are not part of this repository; put them in `test_roms/` (or point
+// CHIP8_TEST_ROMS at a directory holding them) and those cases run too.
+// Locally they are skipped without the ROMs, but on CI (where `CI` is set,
+// and the workflow fetches them) a missing ROM fails. A case whose golden
+// has not been committed yet is skipped everywhere. CHIP8_BLESS=1 writes the
+// screens this emulator draws as goldens; only commit ones that match the
+// suite's own pictures of a passing run.
+
+use std::path::PathBuf;
+use chip8_emulator::cpu::CPU;
+use chip8_emulator::framebuffer::Framebuffer;
+use chip8_emulator::headless::{self, InputScript, RunConfig};
+
+mod common;
+use common::rom;
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name))
+}
+
+fn check_golden(name: &str, display: &Framebuffer) {
+    let path = golden_path(name);
+    let actual = headless::framebuffer_text(display);
+    if std::env::var_os("CHIP8_BLESS").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing golden {}, run with CHIP8_BLESS=1 to create it", path.display()));
+    assert!(expected == actual, "{} differs from golden {}:\n{}", name, path.display(), actual);
+}
+
+fn test_rom(file: &str) -> Option<Vec<u8>> {
+    let dir = std::env::var("CHIP8_TEST_ROMS").unwrap_or_else(|_| "test_roms".to_string());
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(dir).join(file);
+    match std::fs::read(&path) {
+        Ok(rom) => Some(rom),
+        Err(_) if std::env::var_os("CI").is_some() => panic!("{} not found; CI must run the Timendus suite", path.display()),
+        Err(_) => {
+            eprintln!("skipping: {} not found", path.display());
+            None
+        }
+    }
+}
+
+#[test]
+fn font_glyphs() {
+    // draw 0-F in two rows of eight using FX29
+    let mut prog = vec![0x6000, 0x6100, 0x6200]; // V0 = digit, V1 = x, V2 = y
+    for digit in 0..16u16 {
+        prog.extend([0xF029, 0xD125, 0x7001, 0x7108]);
+        if digit == 7 {
+            prog.extend([0x6100, 0x6208]);
+        }
+    }
+    prog.push(0x1200 + prog.len() as u16 * 2); // spin
+    let cpu = headless::run(&rom(&prog), &RunConfig { frames: 10, ..RunConfig::default() }).unwrap();
+    check_golden("font-glyphs", cpu.display());
+}
+
+#[test]
+fn sprite_collision_and_erase() {
+    // drawing the same sprite twice erases it and sets VF
+    let prog = [0xA050, 0x6000, 0xD005, 0xD005, 0x1208];
+    let cpu = headless::run(&rom(&prog), &RunConfig { frames: 2, ..RunConfig::default() }).unwrap();
+    assert_eq!(cpu.registers()[0xF], 1);
+    assert_eq!(headless::framebuffer_hash(cpu.display()), headless::framebuffer_hash(CPU::new().display()));
+}
+
+#[test]
+fn scripted_key_wait() {
+    // FX0A blocks until key A is pressed, then the key's glyph is drawn
+    let prog = [0xF00A, 0xF029, 0x6110, 0xD115, 0x1208];
+    let rom = rom(&prog);
+    let idle = headless::run(&rom, &RunConfig { frames: 20, ..RunConfig::default() }).unwrap();
+    assert_eq!(idle.pc(), 0x200);
+
+    let script = InputScript::parse("# tap A\n5 down a\n8 up a\n").unwrap();
+    let cpu = headless::run(&rom, &RunConfig { frames: 20, script, ..RunConfig::default() }).unwrap();
+    assert_eq!(cpu.registers()[0], 0xA);
+    check_golden("key-wait", cpu.display());
+}
+
+#[test]
+fn delay_timer_counts_frames() {
+    // wait 30 frames on the delay timer, then draw a digit
+    let prog = [0x601E, 0xF015, 0xF007, 0x3000, 0x1204, 0xF029, 0xD005, 0x120C];
+    let rom = rom(&prog);
+    let blank = headless::framebuffer_hash(CPU::new().display());
+    let early = headless::run(&rom, &RunConfig { frames: 25, ..RunConfig::default() }).unwrap();
+    assert_eq!(headless::framebuffer_hash(early.display()), blank);
+    let late = headless::run(&rom, &RunConfig { frames: 35, ..RunConfig::default() }).unwrap();
+    assert_ne!(headless::framebuffer_hash(late.display()), blank);
+}
+
+fn timendus(file: &str, golden: &str, config: RunConfig) -> Option<CPU> {
+    if !golden_path(golden).exists() && std::env::var_os("CHIP8_BLESS").is_none() {
+        eprintln!("skipping: no golden for {} yet", file);
+        return None;
+    }
+    let rom = test_rom(file)?;
+    let cpu = headless::run(&rom, &config).unwrap();
+    check_golden(golden, cpu.display());
+    Some(cpu)
+}
+
+#[test]
+fn timendus_chip8_logo() {
+    timendus("1-chip8-logo.ch8", "timendus-chip8-logo", RunConfig { frames: 40, ..RunConfig::default() });
+}
+
+#[test]
+fn timendus_ibm_logo() {
+    timendus("2-ibm-logo.ch8", "timendus-ibm-logo", RunConfig { frames: 20, ..RunConfig::default() });
+}
+
+#[test]
+fn timendus_corax_plus() {
+    timendus("3-corax+.ch8", "timendus-corax-plus", RunConfig { frames: 40, ..RunConfig::default() });
+}
+
+#[test]
+fn timendus_flags() {
+    timendus("4-flags.ch8", "timendus-flags", RunConfig { frames: 80, ..RunConfig::default() });
+}
+
+#[test]
+fn timendus_quirks() {
+    // 0x1FF = 1 skips the menu and tests the CHIP-8 platform
+    let config = RunConfig { frames: 300, pokes: vec![(0x1FF, 1)], ..RunConfig::default() };
+    timendus("5-quirks.ch8", "timendus-quirks", config);
+}
+
+#[test]
+fn timendus_keypad() {
+    // 0x1FF = 1 selects the EX9E test, which lights up every held key
+    let script = InputScript::new().press(10, 0x5).press(10, 0xC);
+    let config = RunConfig { frames: 30, script, pokes: vec![(0x1FF, 1)], ..RunConfig::default() };
+    timendus("6-keypad.ch8", "timendus-keypad", config);
+}
+
+#[test]
+fn timendus_beep() {
+    // holding B keeps the beep going
+    let script = InputScript::new().press(10, 0xB);
+    let config = RunConfig { frames: 30, script, ..RunConfig::default() };
+    if let Some(cpu) = timendus("7-beep.ch8", "timendus-beep", config) {
+        assert!(cpu.sound_timer() > 0);
+    }
+}
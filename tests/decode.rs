@@ -0,0 +1,58 @@
+use chip8_emulator::cpu::CPU;
+use chip8_emulator::decode::{decode, Op};
+
+mod common;
+
+fn load(words: &[u16], cached: bool) -> CPU {
+    common::load_with(words, |cpu| cpu.set_decode_cache(cached))
+}
+
+#[test]
+fn decodes_operands() {
+    assert_eq!(decode(0x00E0), Op::Clear);
+    assert_eq!(decode(0x7A2B), Op::AddImm(0xA, 0x2B));
+    assert_eq!(decode(0x8CDE), Op::ShiftLeft(0xC, 0xD));
+    assert_eq!(decode(0xB123), Op::JumpOffset(0x1, 0x123));
+    assert_eq!(decode(0xD12F), Op::Draw(0x1, 0x2, 0xF));
+    assert_eq!(decode(0xF455), Op::Store(0x4));
+    assert_eq!(decode(0x8008), Op::Invalid(0x8008));
+    assert_eq!(decode(0xE000), Op::Invalid(0xE000));
+}
+
+#[test]
+fn store_over_cached_code_runs_the_new_instruction() {
+    let rom = [
+        0x6064, // 200: V0 = 64
+        0x6109, // 202: V1 = 09
+        0xA20A, // 204: I = 20A
+        0x1208, // 206: jump 208
+        0x7201, // 208: V2 += 1
+        0x6405, // 20A: V4 = 05, rewritten to V4 = 09
+        0x3202, // 20C: skip the rewrite on the second pass
+        0x1212, // 20E: jump 212
+        0x1210, // 210: spin
+        0xF155, // 212: mem[20A..=20B] = V0, V1
+        0x1208, // 214: run the body again
+    ];
+    for cached in [true, false] {
+        let mut cpu = load(&rom, cached);
+        for _ in 0..4 + 6 + 3 { // setup, first pass with the rewrite, second pass
+            cpu.execute().unwrap();
+        }
+        assert_eq!(cpu.pc(), 0x210);
+        assert_eq!(cpu.registers()[4], 9, "cached: {}", cached);
+    }
+}
+
+#[test]
+fn write_mem_invalidates_the_instruction_it_lands_in() {
+    let mut cpu = load(&[0x7001, 0x1200], true);
+    for _ in 0..4 {
+        cpu.execute().unwrap();
+    }
+    cpu.write_mem(0x201, 0x05); // second byte of the cached 7001
+    for _ in 0..2 {
+        cpu.execute().unwrap();
+    }
+    assert_eq!(cpu.registers()[0], 2 + 5);
+}
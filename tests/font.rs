@@ -0,0 +1,73 @@
+use chip8_emulator::cpu::CPU;
+use chip8_emulator::font::{Font, BIG_SIZE, SMALL_SIZE};
+use chip8_emulator::profile::MachineProfile;
+
+mod common;
+
+fn on(profile: MachineProfile, words: &[u16]) -> CPU {
+    common::load_with(words, |cpu| cpu.set_profile(profile).unwrap())
+}
+
+#[test]
+fn registry() {
+    for name in Font::NAMES {
+        let font = Font::named(name).unwrap();
+        assert_eq!(font.small.len(), SMALL_SIZE);
+        assert_eq!(font.big.is_some(), name == "schip" || name == "octo", "{}", name);
+        assert!(font.big.iter().all(|big| big.len() == BIG_SIZE));
+    }
+    assert!(Font::named("comic").is_err());
+    // each machine draws its own digits
+    let ones: Vec<u8> = ["chip8", "vip", "dream6800", "eti660", "fish"].iter().map(|n| Font::named(n).unwrap().small[5]).collect();
+    assert_eq!(ones, [0x20, 0x60, 0x40, 0x20, 0x40]);
+}
+
+#[test]
+fn machines_bring_their_fonts() {
+    // the 1 glyph at 0,0
+    let mut cpu = on(MachineProfile::vip(), &[0x6001, 0xF029, 0xD115]);
+    cpu.run(3).unwrap();
+    assert!(cpu.display().pixel(1, 0) && cpu.display().pixel(2, 0) && !cpu.display().pixel(1, 1));
+    assert_eq!(cpu.read_mem(0x55), 0x60);
+}
+
+#[test]
+fn big_digits_need_a_big_font() {
+    // FX30 for 7, drawn 10 lines tall
+    let rom = [0x6007, 0xF030, 0xD11A];
+    let profile = MachineProfile { font: Font::named("octo").unwrap(), ..MachineProfile::default() };
+    let mut cpu = on(profile, &rom);
+    cpu.run(3).unwrap();
+    assert_eq!(cpu.index(), 0x50 + 80 + 70);
+    assert_eq!(cpu.read_mem(0x50 + 80 + 70), 0xFF);
+    assert!(cpu.display().pixel(7, 0) && cpu.display().pixel(3, 9) && !cpu.display().pixel(7, 9));
+
+    let mut cpu = on(MachineProfile::default(), &rom);
+    cpu.run(1).unwrap();
+    assert!(cpu.run(1).is_err());
+}
+
+#[test]
+fn font_files() {
+    let dir = std::env::temp_dir().join(format!("chip8-fonts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, len: usize| {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0x81; len]).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let base = Font::named("octo").unwrap();
+
+    let small = Font::parse(&write("small.bin", SMALL_SIZE), &base).unwrap();
+    assert_eq!(small.small, vec![0x81; SMALL_SIZE]);
+    assert_eq!(small.big, base.big);
+
+    let big = Font::parse(&write("big.bin", BIG_SIZE), &base).unwrap();
+    assert_eq!(big.small, base.small);
+    assert_eq!(big.big, Some(vec![0x81; BIG_SIZE]));
+
+    assert!(Font::parse(&write("odd.bin", 81), &base).is_err());
+    assert!(Font::parse(&dir.join("missing.bin").to_string_lossy(), &base).is_err());
+    assert_eq!(Font::parse("vip", &base).unwrap(), Font::named("vip").unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,64 @@
+use chip8_emulator::cpu::{CPU, WIDTH, HEIGHT};
+use chip8_emulator::framebuffer::Framebuffer;
+
+mod common;
+use common::rom;
+
+#[test]
+fn rows_pack_column_zero_in_the_top_bit() {
+    let mut fb: Framebuffer = Framebuffer::new(HEIGHT);
+    assert!(!fb.draw_byte(0, 0, 0b1010_0000, false));
+    assert_eq!(fb.rows()[0], 0xA000_0000_0000_0000);
+    assert!(fb.pixel(0, 0) && !fb.pixel(1, 0) && fb.pixel(2, 0));
+}
+
+#[test]
+fn xor_reports_collisions_and_erases() {
+    let mut fb: Framebuffer = Framebuffer::new(HEIGHT);
+    fb.draw_byte(10, 3, 0xF0, false);
+    assert!(!fb.draw_byte(14, 3, 0xF0, false)); // touching, not overlapping
+    assert!(fb.draw_byte(12, 3, 0x80, false));
+    assert!(!fb.pixel(12, 3));
+    assert_eq!(fb.pixels().filter(|&px| px).count(), 7);
+}
+
+#[test]
+fn right_edge_clips_or_wraps() {
+    let mut fb: Framebuffer = Framebuffer::new(HEIGHT);
+    fb.draw_byte(60, 0, 0xFF, false);
+    assert_eq!(fb.rows()[0], 0xF);
+    fb.draw_byte(60, 1, 0xFF, true);
+    assert_eq!(fb.rows()[1], 0xF000_0000_0000_000F);
+
+    let mut hires: Framebuffer<u128> = Framebuffer::new(64);
+    hires.draw_byte(124, 63, 0xFF, true);
+    assert_eq!(hires.width(), 128);
+    assert!(hires.pixel(127, 63) && hires.pixel(0, 63) && hires.pixel(3, 63) && !hires.pixel(4, 63));
+}
+
+#[test]
+fn only_changed_rows_are_dirty() {
+    let mut fb: Framebuffer = Framebuffer::new(HEIGHT);
+    assert_eq!(fb.take_dirty(), 0xFFFF_FFFF); // a new framebuffer has never been presented
+    fb.draw_byte(0, 5, 0x00, false);
+    fb.draw_byte(0, 7, 0x81, false);
+    fb.set_pixel(3, 9, false); // already dark
+    assert_eq!(fb.take_dirty(), 1 << 7);
+    assert_eq!(fb.take_dirty(), 0);
+}
+
+#[test]
+fn display_buffer_follows_draws_and_clears() {
+    // draw the 0 glyph, present, then clear the screen and present again
+    let rom: Vec<u8> = rom(&[0x6000, 0xF029, 0xD005, 0x00E0]);
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom).unwrap();
+    let mut buffer = [0x123456u32; WIDTH * HEIGHT];
+    cpu.run(3).unwrap();
+    cpu.update_display_buffer(&mut buffer);
+    assert_eq!(&buffer[..4], &[0xFFFFFF; 4]);
+    assert_eq!(buffer[WIDTH * 5], 0); // every row is converted the first time
+    cpu.run(1).unwrap();
+    cpu.update_display_buffer(&mut buffer);
+    assert!(buffer.iter().all(|&px| px == 0));
+}
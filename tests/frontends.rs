@@ -0,0 +1,39 @@
+use chip8_emulator::graphics::{encode_kitty, encode_sixel, GraphicsProtocol};
+use chip8_emulator::terminal::render_half_blocks;
+
+#[test]
+fn half_blocks_pair_rows() {
+    // 2x3 image: the third row is paired with black
+    let buffer = [0xFFFFFF, 0x000000, 0x000000, 0xFFFFFF, 0x808080, 0x000000];
+    let out = render_half_blocks(&buffer, 2, 3);
+    let lines: Vec<&str> = out.split("\r\n").collect();
+    assert_eq!(lines.len(), 3); // two cell rows plus the trailing empty split
+    assert_eq!(lines[0].matches('▀').count(), 2);
+    assert!(lines[0].starts_with("\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m▀"));
+    assert!(lines[0].contains("\x1b[38;2;0;0;0m\x1b[48;2;255;255;255m▀"));
+    assert!(lines[1].starts_with("\x1b[38;2;128;128;128m\x1b[48;2;0;0;0m▀\x1b[38;2;0;0;0m"));
+}
+
+#[test]
+fn sixel_bands_and_runs() {
+    // 8x7 image, left half white: two bands, the second one pixel tall
+    let buffer: Vec<u32> = (0..8 * 7).map(|i| if i % 8 < 4 { 0xFFFFFF } else { 0 }).collect();
+    let out = encode_sixel(&buffer, 8, 7);
+    assert_eq!(
+        out,
+        "\x1bPq\"1;1;8;7#0;2;100;100;100#1;2;0;0;0#0!4~!4?$#1!4?!4~-#0!4@!4?$#1!4?!4@-\x1b\\"
+    );
+}
+
+#[test]
+fn kitty_chunks_and_base64() {
+    let out = encode_kitty(&[0xFF0000, 0x00FF00], 2, 1);
+    assert_eq!(out, "\x1b_Ga=T,f=24,s=2,v=1,i=1,p=1,C=1,q=2,m=0;/wAAAP8A\x1b\\");
+
+    // 64x32 scaled by 8 is well over one 4096 byte chunk
+    let big = GraphicsProtocol::Kitty.encode(&[0xFFFFFF; 64 * 32], 64, 32, 8);
+    let base64_len = 512 * 256 * 3 / 3 * 4;
+    assert_eq!(big.matches("\x1b_G").count(), base64_len / 4096);
+    assert_eq!(big.matches("m=1;").count(), base64_len / 4096 - 1);
+    assert!(big.contains("\x1b_Gm=0;"));
+}
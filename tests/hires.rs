@@ -0,0 +1,100 @@
+use chip8_emulator::cpu::{Quirks, CPU, HEIGHT, HIRES_HEIGHT, WIDTH};
+use chip8_emulator::headless;
+
+mod common;
+
+// A HIRES ROM: the jump at 0x200, the interpreter patch (zeros here) and
+// `code` from 0x2C0, followed by the 0230 every HIRES program has.
+fn hires(code: &[u16]) -> CPU {
+    let mut rom = vec![0x12, 0x60];
+    rom.resize(0xC0, 0);
+    rom.extend(common::rom(code));
+    rom.extend([0x02, 0x30]);
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom).unwrap();
+    cpu
+}
+
+#[test]
+fn signature_switches_to_64x64() {
+    // the 0 glyph at 8,40
+    let mut cpu = hires(&[0x6008, 0x6128, 0xA050, 0xD015]);
+    assert!(cpu.hires());
+    assert_eq!(cpu.pc(), 0x2C0);
+    assert_eq!(cpu.display().height(), HIRES_HEIGHT);
+    cpu.run(4).unwrap();
+    assert!(cpu.display().pixel(8, 40) && cpu.display().pixel(11, 44));
+    assert_eq!(headless::framebuffer_text(cpu.display()).lines().count(), 64);
+
+    let mut buffer = vec![0; WIDTH * HIRES_HEIGHT];
+    cpu.update_display_buffer(&mut buffer);
+    assert_eq!(buffer[40 * WIDTH + 8], 0xFFFFFF);
+}
+
+#[test]
+fn plain_roms_stay_64x32() {
+    let rom = [0x12, 0x00];
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom).unwrap();
+    assert!(!cpu.hires());
+    assert_eq!(cpu.display().height(), HEIGHT);
+    assert_eq!(cpu.pc(), 0x200);
+}
+
+#[test]
+fn plain_roms_jumping_to_0x260_stay_64x32() {
+    // jumps over 0x5E bytes of data to code at 0x260 that draws the 0 glyph
+    let mut rom = vec![0x12, 0x60];
+    rom.resize(0x60, 0xAA);
+    rom.extend(common::rom(&[0xA050, 0xD005, 0x1264]));
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom).unwrap();
+    assert!(!cpu.hires());
+    assert_eq!(cpu.display().height(), HEIGHT);
+    assert_eq!(cpu.pc(), 0x200);
+    cpu.run(3).unwrap();
+    assert_eq!(cpu.pc(), 0x264);
+    assert!(cpu.display().pixel(0, 0));
+}
+
+#[test]
+fn clear_is_0230() {
+    let mut cpu = hires(&[0xA050, 0xD005, 0x0230]);
+    cpu.run(2).unwrap();
+    assert!(cpu.display().pixels().any(|px| px));
+    cpu.run(1).unwrap();
+    assert!(cpu.display().pixels().all(|px| !px));
+
+    // anywhere else it is still a machine code call
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&[0x02, 0x30]).unwrap();
+    assert!(cpu.run(1).is_err());
+}
+
+#[test]
+fn sprites_clip_and_wrap_at_row_64() {
+    // the 0 glyph at y 70 (row 6), then at row 62, which loses its last 3 lines unless wrapping
+    let rom = [0x6046, 0xA050, 0xD105, 0x603E, 0xD105];
+    let mut cpu = hires(&rom);
+    cpu.run(5).unwrap();
+    assert!(cpu.display().pixel(0, 6) && cpu.display().pixel(0, 63));
+    assert!(!cpu.display().pixel(0, 0));
+
+    let mut cpu = hires(&rom);
+    cpu.set_quirks(Quirks { wrap_sprites: true, ..Quirks::default() });
+    cpu.run(5).unwrap();
+    assert!(cpu.display().pixel(0, 0) && cpu.display().pixel(1, 2));
+}
+
+#[test]
+fn save_states_keep_the_geometry() {
+    let mut cpu = hires(&[0x6130, 0xA050, 0xD015]);
+    cpu.run(3).unwrap();
+    let state = cpu.save_state().unwrap();
+    assert_eq!(state.len(), CPU::state_size());
+
+    let mut restored = CPU::new();
+    restored.load_state(&state).unwrap();
+    assert!(restored.hires());
+    assert_eq!(restored.display(), cpu.display());
+}
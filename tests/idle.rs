@@ -0,0 +1,81 @@
+// Skipping idle loops must leave the machine exactly as running them does.
+
+use chip8_emulator::cpu::IdleLoop;
+use chip8_emulator::timing::{Timing, VipTiming};
+
+mod common;
+use common::load;
+
+// counts delay-timer waits of 3 frames in V1, and stops at a key wait after 4
+const WAITS: [u16; 10] = [
+    0x6003, 0xF015, // 200: delay = 3
+    0xF207, 0x3200, 0x1204, // 204: wait for it
+    0x7101, 0x3104, 0x1202, // 20A: count, and again until V1 = 4
+    0xF30A, 0x1200, // 210: wait for a key, then start over
+];
+
+#[test]
+fn recognises_idle_loops() {
+    let mut cpu = load(&WAITS);
+    assert_eq!(cpu.idle_loop(), None);
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.idle_loop(), Some(IdleLoop::Delay { x: 2 }));
+    cpu.tick_timers();
+    cpu.tick_timers();
+    cpu.tick_timers();
+    assert_eq!(cpu.idle_loop(), None, "the timer has run out");
+
+    let mut cpu = load(&[0xF30A]);
+    assert_eq!(cpu.idle_loop(), Some(IdleLoop::Key));
+    cpu.set_keypad([true; 16]);
+    assert_eq!(cpu.idle_loop(), None, "a key press is waiting");
+
+    // a loop that goes anywhere but back to its FX07 is not idle
+    let mut cpu = load(&[0x6003, 0xF015, 0xF207, 0x3200, 0x1206]);
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.idle_loop(), None);
+}
+
+#[test]
+fn skipping_matches_running() {
+    let timings = [
+        Timing::Fixed { ipf: 1 },
+        Timing::Fixed { ipf: 7 },
+        Timing::Fixed { ipf: 1000 }, // with the next two, frames start at each point in the loop
+        Timing::Fixed { ipf: 1001 },
+        Timing::Fixed { ipf: 1002 },
+        Timing::Vip(VipTiming::new()),
+    ];
+    for timing in timings {
+        for jit in [false, true] {
+            let mut skipping = load(&WAITS);
+            let mut running = load(&WAITS);
+            running.set_idle_skip(false);
+            skipping.set_jit(jit);
+            running.set_jit(jit);
+            let (mut a, mut b) = (timing.clone(), timing.clone());
+            for frame in 0..40 {
+                let keypad = [frame % 17 == 16; 16];
+                skipping.set_keypad(keypad);
+                running.set_keypad(keypad);
+                a.run_frame(&mut skipping).unwrap();
+                b.run_frame(&mut running).unwrap();
+                let context = format!("{:?}, jit {}, frame {}", timing, jit, frame);
+                assert_eq!(skipping.cycle(), running.cycle(), "{}", context);
+                assert_eq!(skipping.save_state().unwrap(), running.save_state().unwrap(), "{}", context);
+            }
+        }
+    }
+}
+
+#[test]
+fn idle_frames_cost_nothing() {
+    // without skipping, each of these frames would run four billion instructions
+    let mut cpu = load(&WAITS);
+    for _ in 0..20 {
+        cpu.run_frame(u32::MAX).unwrap();
+    }
+    assert_eq!(cpu.registers()[1], 4);
+    assert_eq!(cpu.pc(), 0x210);
+    assert_eq!(cpu.cycle(), 20 * u32::MAX as u64);
+}
@@ -0,0 +1,167 @@
+// Differential tests: compiled blocks must leave the machine exactly as the
+// interpreter does, after any number of instructions.
+
+use chip8_emulator::cpu::{Quirks, CPU};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+mod common;
+use common::rom;
+
+const PROGRAM_WORDS: usize = 48;
+
+// random instruction that cannot panic the interpreter: no returns or calls.
+// I sometimes starts near the end of memory, and FX1E and the increment_i
+// quirk move it on, so memory ops run past the end and have to fail
+fn random_instr(rng: &mut StdRng) -> u16 {
+    let x = rng.random_range(0..16u16) << 8;
+    let y = rng.random_range(0..16u16) << 4;
+    let nn = rng.random_range(0..256u16);
+    let target = 0x200 + 2 * rng.random_range(0..PROGRAM_WORDS as u16);
+    match rng.random_range(0..16) {
+        0 => 0x00E0,
+        1 => 0x1000 | target,
+        2 => 0x3000 | x | nn,
+        3 => 0x4000 | x | nn,
+        4 => 0x5000 | x | y,
+        5 | 6 => 0x6000 | x | nn,
+        7 => 0x7000 | x | nn,
+        8 | 9 => 0x8000 | x | y | [0, 1, 2, 3, 4, 5, 6, 7, 0xE][rng.random_range(0..9)],
+        10 => 0x9000 | x | y,
+        11 if rng.random_bool(0.2) => 0xA000 | rng.random_range(0xFF0..0x1000u16),
+        11 => 0xA000 | rng.random_range(0x200..0x280u16), // reads and writes land on the code
+        12 => 0xD000 | x | y | rng.random_range(0..16u16),
+        13 => 0xE000 | x | [0x9E, 0xA1][rng.random_range(0..2)],
+        14 => 0xF000 | x | [0x07, 0x15, 0x18, 0x1E, 0x29, 0x30, 0x33][rng.random_range(0..7)], // no big font, so FX30 fails
+        _ => 0xF000 | x | [0x55, 0x65][rng.random_range(0..2)],
+    }
+}
+
+fn machines(rom: &[u8], quirks: Quirks) -> (CPU, CPU) {
+    let mut interp = CPU::new();
+    let mut jit = CPU::new();
+    jit.set_jit(true);
+    for cpu in [&mut interp, &mut jit] {
+        cpu.set_quirks(quirks);
+        cpu.load_bytes(rom).unwrap();
+    }
+    (interp, jit)
+}
+
+fn assert_same(interp: &CPU, jit: &CPU, context: &str) {
+    assert_eq!(interp.cycle(), jit.cycle(), "{}", context);
+    assert_eq!(interp.pc(), jit.pc(), "{}", context);
+    assert_eq!(interp.save_state().unwrap(), jit.save_state().unwrap(), "{}", context);
+}
+
+#[test]
+fn random_programs_match_the_interpreter() {
+    let mut rng = StdRng::seed_from_u64(0xC8);
+    for program in 0..200 {
+        let rom: Vec<u8> = (0..PROGRAM_WORDS).flat_map(|_| random_instr(&mut rng).to_be_bytes()).collect();
+        let quirks = if program % 2 == 0 {
+            Quirks::default()
+        } else {
+            Quirks { vf_reset: true, increment_i: true, shift_vx: true, jump_vx: true, wrap_sprites: true }
+        };
+        let (mut interp, mut jit) = machines(&rom, quirks);
+        for step in 0..100 {
+            if (0x200..0x1000).step_by(2).any(|addr| interp.read_mem(addr) & 0xF0 == 0xC0) {
+                break; // a write has made a CXNN, and the two random numbers would differ
+            }
+            let keypad: [bool; 16] = std::array::from_fn(|_| rng.random_bool(0.2));
+            interp.set_keypad(keypad);
+            jit.set_keypad(keypad);
+            let count = rng.random_range(1..40);
+            let (a, b) = (interp.run(count), jit.run(count));
+            assert_eq!(a, b, "program {} step {}", program, step);
+            assert_same(&interp, &jit, &format!("program {} step {}", program, step));
+            if a.is_err() {
+                break; // both ran into the same data as code
+            }
+            interp.tick_timers();
+            jit.tick_timers();
+        }
+    }
+}
+
+#[test]
+fn rewritten_block_is_recompiled() {
+    // 20A sits in the middle of the loop's block; FX55 rewrites it from V4 += 1 to V4 += 9
+    let rom = rom(&[
+        0x6074, 0x6109, 0xA20A, 0x1208, // setup, then into the loop
+        0x7201, 0x7401, 0x3202, 0x1212, // 208: loop body, skip to 210 on the second pass
+        0x1210, 0xF155, 0x1208, // 210: spin; 212: rewrite 20A and go round again
+    ]);
+    let (mut interp, mut jit) = machines(&rom, Quirks::default());
+    for cpu in [&mut interp, &mut jit] {
+        cpu.run(4 + 6 + 3).unwrap();
+        assert_eq!(cpu.pc(), 0x210);
+        assert_eq!(cpu.registers()[4], 1 + 9);
+    }
+    assert_same(&interp, &jit, "after rewrite");
+}
+
+#[test]
+fn partial_blocks_stop_on_the_exact_instruction() {
+    let rom: Vec<u8> = rom(&[0x7001, 0x7101, 0x7201, 0x7301, 0x1200]);
+    let (mut interp, mut jit) = machines(&rom, Quirks::default());
+    for count in [1, 2, 3, 7, 11] {
+        interp.run(count).unwrap();
+        jit.run(count).unwrap();
+        assert_same(&interp, &jit, &format!("after {} more", count));
+    }
+}
+
+#[test]
+fn failing_big_font_stops_where_the_interpreter_does() {
+    // the default font has no big digits, so FX30 fails in the middle of the block
+    let rom = rom(&[0x6001, 0xF030, 0x6102, 0x1200]);
+    let (mut interp, mut jit) = machines(&rom, Quirks::default());
+    assert!(interp.run(4).is_err());
+    assert!(jit.run(4).is_err());
+    assert_eq!(jit.pc(), 0x204);
+    assert_same(&interp, &jit, "FX30");
+}
+
+#[test]
+fn memory_ops_past_the_end_fail_where_the_interpreter_does() {
+    // FX1E takes I to 0xFFE, so the FX65 in the middle of the block would read past the end
+    let rom = rom(&[0x600E, 0xAFF0, 0xF01E, 0xF265, 0x6102, 0x1200]);
+    let (mut interp, mut jit) = machines(&rom, Quirks::default());
+    let err = Err("I 0xFFE + 3 is past the end of memory".to_string());
+    assert_eq!(interp.run(6), err);
+    assert_eq!(jit.run(6), err);
+    assert_eq!(jit.pc(), 0x208);
+    assert_same(&interp, &jit, "FX65");
+}
+
+#[test]
+fn drawing_over_the_running_block_with_the_display_in_memory() {
+    // code at 0xF00 clears the screen, and so zeros the instructions after it
+    let mut words = vec![0x1F00];
+    words.resize((0xF00 - 0x200) / 2, 0);
+    words.extend([0x00E0, 0x6005, 0x1F04]);
+    let mut cpus = [CPU::new(), CPU::new()];
+    cpus[1].set_jit(true);
+    for cpu in &mut cpus {
+        cpu.set_memory_display(true);
+        cpu.load_bytes(&rom(&words)).unwrap();
+        assert!(cpu.run(4).is_err()); // 0000 is not an instruction
+        assert_eq!(cpu.pc(), 0xF04);
+        assert_eq!(cpu.registers()[0], 0);
+    }
+    assert_same(&cpus[0], &cpus[1], "memory display");
+}
+
+#[test]
+fn running_off_the_end_of_memory_fails() {
+    for (v0, pc) in [(0x00, 0xFFF), (0x10, 0x100F)] {
+        let (mut interp, mut jit) = machines(&rom(&[0x6000 | v0, 0xBFFF]), Quirks::default());
+        for cpu in [&mut interp, &mut jit] {
+            assert_eq!(cpu.run(3), Err(format!("PC 0x{:03X} is past the end of memory", pc)));
+            assert_eq!(cpu.pc(), pc);
+        }
+        assert_same(&interp, &jit, "past the end");
+    }
+}
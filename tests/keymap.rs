@@ -0,0 +1,35 @@
+use chip8_emulator::keymap::KeyMap;
+
+#[test]
+fn default_layouts_cover_both_keypads() {
+    let keys = KeyMap::default();
+    assert_eq!(keys.lookup('x'), Some((0, 0x0)));
+    assert_eq!(keys.lookup('4'), Some((0, 0xC)));
+    assert_eq!(keys.lookup('V'), Some((0, 0xF)));
+    assert_eq!(keys.lookup(','), Some((1, 0x0)));
+    assert_eq!(keys.lookup('p'), Some((1, 0xD)));
+    assert_eq!(keys.lookup('/'), Some((1, 0xF)));
+    assert_eq!(keys.lookup('g'), None);
+}
+
+#[test]
+fn layouts_can_be_replaced() {
+    // 1-4 and so on are on the default first keypad, i, j and others on the second
+    assert_eq!(KeyMap::new(KeyMap::FIRST, "0123456789ABCDEF"), Err("Key '1' is on both keypads".to_string()));
+    assert!(KeyMap::new("ghijklmnopqrstuv", KeyMap::SECOND).is_err());
+    // replacing both at once works, whichever one would have clashed first
+    let keys = KeyMap::new("0123456789ABCDEF", "ghyn[]-=;'`\\ ,./").unwrap();
+    assert_eq!(keys, KeyMap::new("0123456789abcdef", "ghyn[]-=;'`\\ ,./").unwrap());
+    assert_eq!(keys.lookup('g'), Some((1, 0x0)));
+    assert_eq!(keys.lookup(' '), Some((1, 0xC)));
+    assert_eq!(keys.lookup('u'), None);
+    assert_eq!(keys.lookup('b'), Some((0, 0xB))); // not case sensitive
+    assert_eq!(keys.lookup('x'), None);
+}
+
+#[test]
+fn bad_layouts_are_refused() {
+    assert!(KeyMap::parse("x123").is_err());
+    assert!(KeyMap::parse("x123qweasdzc4rfvv").is_err());
+    assert_eq!(KeyMap::parse("x123qweasdzc4rfX"), Err("Key 'x' is used twice in x123qweasdzc4rfX".to_string()));
+}
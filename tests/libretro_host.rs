@@ -0,0 +1,191 @@
+// A minimal libretro frontend: loads the core from the cdylib that cargo builds
+// alongside the tests and drives it through the C API only.
+
+use std::ffi::{c_uint, c_void, CStr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use chip8_emulator::libretro::{
+    AudioSampleBatchFn, EnvironmentFn, InputPollFn, InputStateFn, RetroGameInfo, RetroInputDescriptor,
+    RetroSystemAvInfo, RetroSystemInfo, RetroVariable, VideoRefreshFn,
+};
+use libloading::{Library, Symbol};
+
+mod common;
+
+static DESCRIPTORS: AtomicUsize = AtomicUsize::new(0);
+static AUDIO_FRAMES: AtomicUsize = AtomicUsize::new(0);
+static HOLD_UP: AtomicBool = AtomicBool::new(false);
+static FRAME: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+// core options the host reports, besides chip8_ipf, and whether they changed
+static OPTIONS: Mutex<Vec<(&CStr, &CStr)>> = Mutex::new(Vec::new());
+static UPDATED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        10 => true, // SET_PIXEL_FORMAT
+        11 => {
+            // SET_INPUT_DESCRIPTORS, terminated by a null description
+            let mut d = data as *const RetroInputDescriptor;
+            let mut count = 0;
+            while !(*d).description.is_null() {
+                count += 1;
+                d = d.add(1);
+            }
+            DESCRIPTORS.store(count, Ordering::SeqCst);
+            true
+        }
+        15 => {
+            // GET_VARIABLE: run at 20 instructions per frame, everything else
+            // default unless set in OPTIONS
+            let var = data as *mut RetroVariable;
+            let key = CStr::from_ptr((*var).key);
+            let options = OPTIONS.lock().unwrap();
+            let value = match options.iter().find(|(k, _)| *k == key) {
+                Some((_, v)) => *v,
+                None if key == c"chip8_ipf" => c"20",
+                None => return false,
+            };
+            (*var).value = value.as_ptr();
+            true
+        }
+        16 => true, // SET_VARIABLES
+        17 => {
+            // GET_VARIABLE_UPDATE
+            *(data as *mut bool) = UPDATED.swap(false, Ordering::SeqCst);
+            true
+        }
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    assert_eq!(pitch, width as usize * 4);
+    let pixels = std::slice::from_raw_parts(data as *const u32, (width * height) as usize);
+    *FRAME.lock().unwrap() = pixels.to_vec();
+}
+
+unsafe extern "C" fn audio_batch(_data: *const i16, frames: usize) -> usize {
+    AUDIO_FRAMES.fetch_add(frames, Ordering::SeqCst);
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(_port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    (device == 1 && id == 4 && HOLD_UP.load(Ordering::SeqCst)) as i16 // d-pad up
+}
+
+fn core_path() -> PathBuf {
+    let name = format!("{}chip8_emulator{}", std::env::consts::DLL_PREFIX, std::env::consts::DLL_SUFFIX);
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    [deps.join(&name), deps.parent().unwrap().join(&name)]
+        .into_iter()
+        .find(|p| p.exists())
+        .expect("libretro core not built")
+}
+
+fn lit_pixels() -> usize {
+    FRAME.lock().unwrap().iter().filter(|&&px| px != 0).count()
+}
+
+#[test]
+fn core_runs_under_a_host() {
+    unsafe {
+        let lib = Library::new(core_path()).unwrap();
+        macro_rules! sym {
+            ($name:literal, $ty:ty) => {{
+                let s: Symbol<$ty> = lib.get($name).unwrap();
+                s
+            }};
+        }
+
+        assert_eq!(sym!(b"retro_api_version", unsafe extern "C" fn() -> c_uint)(), 1);
+        sym!(b"retro_set_environment", unsafe extern "C" fn(EnvironmentFn))(environment);
+        sym!(b"retro_set_video_refresh", unsafe extern "C" fn(VideoRefreshFn))(video_refresh);
+        sym!(b"retro_set_audio_sample_batch", unsafe extern "C" fn(AudioSampleBatchFn))(audio_batch);
+        sym!(b"retro_set_input_poll", unsafe extern "C" fn(InputPollFn))(input_poll);
+        sym!(b"retro_set_input_state", unsafe extern "C" fn(InputStateFn))(input_state);
+        sym!(b"retro_init", unsafe extern "C" fn())();
+
+        let mut info: RetroSystemInfo = std::mem::zeroed();
+        sym!(b"retro_get_system_info", unsafe extern "C" fn(*mut RetroSystemInfo))(&mut info);
+        assert_eq!(CStr::from_ptr(info.valid_extensions), c"ch8|c8");
+        assert!(!info.need_fullpath);
+
+        // wait for a key with FX0A, then draw its glyph
+        let rom: Vec<u8> = common::rom(&[0xF00A, 0xF029, 0x6110, 0xD115, 0x1208]);
+        let game = RetroGameInfo {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        assert!(sym!(b"retro_load_game", unsafe extern "C" fn(*const RetroGameInfo) -> bool)(&game));
+        assert_eq!(DESCRIPTORS.load(Ordering::SeqCst), 16);
+
+        let mut av: RetroSystemAvInfo = std::mem::zeroed();
+        sym!(b"retro_get_system_av_info", unsafe extern "C" fn(*mut RetroSystemAvInfo))(&mut av);
+        assert_eq!((av.geometry.base_width, av.geometry.base_height), (64, 32));
+
+        let run = sym!(b"retro_run", unsafe extern "C" fn());
+        for _ in 0..5 {
+            run();
+        }
+        assert_eq!(lit_pixels(), 0);
+        assert_eq!(AUDIO_FRAMES.load(Ordering::SeqCst), 5 * 735);
+
+        HOLD_UP.store(true, Ordering::SeqCst); // d-pad up is CHIP-8 key 2
+        run();
+        HOLD_UP.store(false, Ordering::SeqCst);
+        run();
+        let drawn = lit_pixels();
+        assert_eq!(drawn, 14); // pixels in the "2" glyph
+
+        let size = sym!(b"retro_serialize_size", unsafe extern "C" fn() -> usize)();
+        let mut state = vec![0u8; size];
+        assert!(sym!(b"retro_serialize", unsafe extern "C" fn(*mut c_void, usize) -> bool)(
+            state.as_mut_ptr() as *mut c_void,
+            size
+        ));
+
+        sym!(b"retro_reset", unsafe extern "C" fn())();
+        run();
+        assert_eq!(lit_pixels(), 0);
+
+        assert!(sym!(b"retro_unserialize", unsafe extern "C" fn(*const c_void, usize) -> bool)(
+            state.as_ptr() as *const c_void,
+            size
+        ));
+        run();
+        assert_eq!(lit_pixels(), drawn);
+        sym!(b"retro_unload_game", unsafe extern "C" fn())();
+
+        // a ROM that fills memory from 0x200 does not fit at the CHIP-8X load
+        // address, so switching platform keeps the game running as it was
+        let mut rom = common::rom(&[0x6002, 0xF029, 0x6110, 0xD115, 0x1208]);
+        rom.resize(0xE00, 0);
+        let game = RetroGameInfo { data: rom.as_ptr() as *const c_void, size: rom.len(), ..game };
+        assert!(sym!(b"retro_load_game", unsafe extern "C" fn(*const RetroGameInfo) -> bool)(&game));
+        run();
+        assert_eq!(lit_pixels(), 14);
+        OPTIONS.lock().unwrap().push((c"chip8_platform", c"chip8x"));
+        UPDATED.store(true, Ordering::SeqCst);
+        run();
+        assert_eq!(lit_pixels(), 14);
+        sym!(b"retro_reset", unsafe extern "C" fn())();
+        run();
+        assert_eq!(lit_pixels(), 14);
+        // nor does it fit on the ETI 660, whose programs start at 0x600
+        *OPTIONS.lock().unwrap() = vec![(c"chip8_machine", c"eti660")];
+        UPDATED.store(true, Ordering::SeqCst);
+        run();
+        assert_eq!(lit_pixels(), 14);
+        sym!(b"retro_get_system_av_info", unsafe extern "C" fn(*mut RetroSystemAvInfo))(&mut av);
+        assert_eq!((av.geometry.base_width, av.geometry.base_height), (64, 32));
+
+        sym!(b"retro_unload_game", unsafe extern "C" fn())();
+        sym!(b"retro_deinit", unsafe extern "C" fn())();
+    }
+}
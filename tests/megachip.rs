@@ -0,0 +1,134 @@
+use chip8_emulator::cpu::{Platform, CPU};
+use chip8_emulator::megachip::{MegaChip, HEIGHT, WIDTH};
+
+mod common;
+
+const PALETTE: usize = 0x10000; // past the usual 4K, where only 01NN NNNN reaches
+const SPRITE: usize = 0x20000;
+
+fn load(words: &[u16]) -> CPU {
+    let mut cpu = common::load_with(words, |cpu| cpu.set_platform(Platform::MegaChip));
+    // red and green, then a 2x1 sprite using them
+    for (n, &b) in [0xFF, 0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00].iter().enumerate() {
+        cpu.write_mem(PALETTE + n, b);
+    }
+    cpu.write_mem(SPRITE, 1);
+    cpu.write_mem(SPRITE + 1, 2);
+    cpu
+}
+
+// MegaChip mode on, the palette, I at the sprite and its size: 6 instructions
+const SETUP: [u16; 8] = [0x0011, 0x0101, 0x0000, 0x0202, 0x0102, 0x0000, 0x0302, 0x0401];
+
+fn program(rest: &[u16]) -> Vec<u16> {
+    SETUP.iter().chain(rest).copied().collect()
+}
+
+fn picture(cpu: &mut CPU) -> Vec<u32> {
+    let mut buffer = vec![0; WIDTH * HEIGHT];
+    cpu.update_display_buffer(&mut buffer);
+    buffer
+}
+
+#[test]
+fn sprites_show_after_00e0() {
+    let rom = program(&[0x600A, 0x6105, 0xD010, 0x00E0]);
+    for jit in [false, true] {
+        let mut cpu = load(&rom);
+        cpu.set_jit(jit);
+        assert_eq!(cpu.output_size(), (WIDTH, HEIGHT));
+        cpu.run(9).unwrap();
+        assert_eq!(cpu.index(), SPRITE as u32);
+        assert!(picture(&mut cpu).iter().all(|&px| px == 0)); // drawn, but not shown yet
+        cpu.run(1).unwrap();
+        let after = picture(&mut cpu);
+        assert_eq!(after[5 * WIDTH + 10], 0xFF0000);
+        assert_eq!(after[5 * WIDTH + 11], 0x00FF00);
+        assert_eq!(after[5 * WIDTH + 12], 0);
+    }
+}
+
+#[test]
+fn collision_colour_sets_vf() {
+    // the sprite twice in the same place with collision colour 1, then once elsewhere
+    let mut cpu = load(&program(&[0x0901, 0xD000, 0xD000, 0x6F07, 0x6020, 0xD000]));
+    cpu.run(8).unwrap();
+    assert_eq!(cpu.registers()[0xF], 0);
+    cpu.run(1).unwrap();
+    assert_eq!(cpu.registers()[0xF], 1);
+    cpu.run(3).unwrap();
+    assert_eq!(cpu.registers()[0xF], 0);
+}
+
+#[test]
+fn blending_and_screen_alpha() {
+    // the sprite at 0,0, then again one to the right at 50%, then the screen at half brightness
+    let mut cpu = load(&program(&[0xD000, 0x0802, 0x6001, 0xD010, 0x00E0]));
+    cpu.run(11).unwrap();
+    let mixed = picture(&mut cpu);
+    assert_eq!(mixed[..3], [0xFF0000, 0x7F7F00, 0x007F00]);
+
+    let mut cpu = load(&program(&[0xD000, 0x00E0, 0x0580]));
+    cpu.run(8).unwrap();
+    assert_eq!(picture(&mut cpu)[0], 0xFF0000);
+    cpu.run(1).unwrap();
+    assert_eq!(picture(&mut cpu)[0], 0x800000);
+}
+
+#[test]
+fn chip8_mode_is_scaled_into_the_middle() {
+    // the 0 glyph at 0,0 without turning MegaChip mode on
+    let mut cpu = load(&[0xA050, 0xD005]);
+    cpu.run(2).unwrap();
+    let buffer = picture(&mut cpu);
+    let top = (HEIGHT - 32 * 4) / 2;
+    assert_eq!(buffer[(top - 1) * WIDTH], 0);
+    assert_eq!(buffer[top * WIDTH], 0xFFFFFF);
+    assert_eq!(buffer[(top + 3) * WIDTH + 15], 0xFFFFFF);
+    assert_eq!(buffer[(top + 4) * WIDTH + 4], 0);
+}
+
+#[test]
+fn samples_play_once_or_loop() {
+    // 8000Hz, 4 samples
+    let header = [0x1F, 0x40, 0x00, 0x00, 0x04, 0x00, 0x80, 0xFF, 0x00, 0x80];
+    for looping in [false, true] {
+        let mut cpu = load(&[0xA300, if looping { 0x0600 } else { 0x0601 }, 0x0700]);
+        for (n, &b) in header.iter().enumerate() {
+            cpu.write_mem(0x300 + n, b);
+        }
+        cpu.run(2).unwrap();
+        let mega = cpu.megachip_mut().unwrap();
+        assert!(mega.playing());
+        let mut out = [1; 6];
+        mega.mix(&mut out, 8000);
+        let expected = if looping { [0, 0x7F00, -0x8000, 0, 0, 0x7F00] } else { [0, 0x7F00, -0x8000, 0, 0, 0] };
+        assert_eq!(out, expected);
+        assert_eq!(mega.playing(), looping);
+        cpu.run(1).unwrap();
+        assert!(!cpu.megachip().unwrap().playing());
+    }
+}
+
+#[test]
+fn short_fast_samples_loop_many_times_a_step() {
+    // a 1-byte looping sample at 65535Hz wraps about once and a half per 44100Hz step
+    let mut mega = MegaChip::new();
+    mega.play(&[0xFF, 0xFF, 0x00, 0x00, 0x01, 0x00, 0x80], true);
+    let mut out = [1; 735];
+    mega.mix(&mut out, 44100);
+    assert!(out.iter().all(|&v| v == 0));
+    assert!(mega.playing());
+}
+
+#[test]
+fn only_on_the_megachip_platform() {
+    let cpu = load(&SETUP);
+    assert!(cpu.save_state().is_err());
+
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&[0x00, 0x11]).unwrap();
+    assert!(cpu.megachip().is_none());
+    assert!(cpu.run(1).is_err()); // a machine code call, as ever
+    assert_eq!(Platform::parse("megachip").unwrap(), Platform::MegaChip);
+}
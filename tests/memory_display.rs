@@ -0,0 +1,49 @@
+use chip8_emulator::cpu::CPU;
+
+mod common;
+
+fn load(words: &[u16], memory_display: bool) -> CPU {
+    common::load_with(words, |cpu| cpu.set_memory_display(memory_display))
+}
+
+#[test]
+fn fx65_reads_pixels() {
+    // draw the font's 0 at 8,1, then read the start of row 1 back
+    let mut cpu = load(&[0x6008, 0x6101, 0xA050, 0xD015, 0xAF08, 0xF365], true);
+    cpu.run(6).unwrap();
+    assert_eq!(cpu.registers()[..4], [0x00, 0xF0, 0x00, 0x00]);
+    assert_eq!(cpu.read_mem(0xF11), 0x90);
+    assert_eq!(cpu.read_mem(0xF29), 0xF0);
+    assert_eq!(cpu.read_mem(0xF31), 0x00);
+}
+
+#[test]
+fn fx55_writes_pixels() {
+    // row 2 = FF 81, then a sprite over it collides
+    let mut cpu = load(&[0x60FF, 0x6181, 0xAF10, 0xF155, 0x6200, 0x6302, 0xA050, 0xD231], true);
+    cpu.run(4).unwrap();
+    let lit: Vec<usize> = (0..64).filter(|&x| cpu.display().pixel(x, 2)).collect();
+    assert_eq!(lit, [0, 1, 2, 3, 4, 5, 6, 7, 8, 15]);
+    assert!(cpu.display_flag);
+    cpu.run(4).unwrap();
+    assert_eq!(cpu.registers()[0xF], 1);
+    assert_eq!([cpu.read_mem(0xF10), cpu.read_mem(0xF11)], [0x0F, 0x81]);
+}
+
+#[test]
+fn clear_empties_the_memory() {
+    let mut cpu = load(&[0xA050, 0xD00F, 0x00E0], true);
+    cpu.run(2).unwrap();
+    assert!((0xF00..0x1000).any(|a| cpu.read_mem(a) != 0));
+    cpu.run(1).unwrap();
+    assert!((0xF00..0x1000).all(|a| cpu.read_mem(a) == 0));
+}
+
+#[test]
+fn off_by_default() {
+    let mut cpu = load(&[0x60FF, 0xAF00, 0xF055, 0xA050, 0xD005], false);
+    cpu.run(5).unwrap();
+    assert!(!cpu.display().pixel(8, 0));
+    assert_eq!(cpu.registers()[0xF], 0);
+    assert_eq!(cpu.read_mem(0xF00), 0xFF);
+}
@@ -0,0 +1,107 @@
+use chip8_emulator::phosphor::{DisplayFilter, DisplayMode};
+
+const LIT: u32 = 0xFFFFFF;
+
+#[test]
+fn modes_parse_with_defaults_and_arguments() {
+    assert_eq!(DisplayMode::parse("direct"), Ok(DisplayMode::Direct));
+    assert_eq!(DisplayMode::parse("phosphor"), Ok(DisplayMode::Phosphor { decay: 0.6 }));
+    assert_eq!(DisplayMode::parse("phosphor:0.25"), Ok(DisplayMode::Phosphor { decay: 0.25 }));
+    assert_eq!(DisplayMode::parse("phosphor:0"), Ok(DisplayMode::Phosphor { decay: 0.0 }));
+    assert_eq!(DisplayMode::parse("blend"), Ok(DisplayMode::Blend { frames: 3 }));
+    assert_eq!(DisplayMode::parse("blend:5"), Ok(DisplayMode::Blend { frames: 5 }));
+}
+
+#[test]
+fn bad_modes_are_rejected() {
+    for bad in ["crt", "phosphor:1", "phosphor:1.5", "phosphor:-0.1", "phosphor:x", "blend:0", "blend:two", ""] {
+        assert!(DisplayMode::parse(bad).is_err(), "{:?} should not parse", bad);
+    }
+}
+
+#[test]
+fn direct_leaves_the_buffer_alone() {
+    let mut filter = DisplayFilter::new(DisplayMode::Direct);
+    let mut buffer = [LIT, 0, 0x123456, 0];
+    filter.apply(&mut buffer);
+    assert_eq!(buffer, [LIT, 0, 0x123456, 0]);
+}
+
+#[test]
+fn phosphor_fades_switched_off_pixels() {
+    let mut filter = DisplayFilter::new(DisplayMode::Phosphor { decay: 0.5 });
+    let mut buffer = [LIT, 0];
+    filter.apply(&mut buffer);
+    assert_eq!(buffer, [LIT, 0]);
+
+    let mut levels = Vec::new();
+    for _ in 0..3 {
+        let mut buffer = [0, 0];
+        filter.apply(&mut buffer);
+        levels.push(buffer[0]);
+        assert_eq!(buffer[1], 0);
+    }
+    assert_eq!(levels, [0x7F7F7F, 0x3F3F3F, 0x1F1F1F]);
+
+    let mut buffer = [LIT, 0]; // lights fully at once
+    filter.apply(&mut buffer);
+    assert_eq!(buffer[0], LIT);
+}
+
+#[test]
+fn colours_fade_and_blend_channel_by_channel() {
+    let mut filter = DisplayFilter::new(DisplayMode::Phosphor { decay: 0.5 });
+    let mut buffer = [0xFF0000, 0x204080];
+    filter.apply(&mut buffer);
+    assert_eq!(buffer, [0xFF0000, 0x204080]); // a CHIP-8X background stays as it is
+
+    let mut buffer = [0x0000FF, 0x204080]; // red fades while blue lights
+    filter.apply(&mut buffer);
+    assert_eq!(buffer, [0x7F00FF, 0x204080]);
+
+    let mut filter = DisplayFilter::new(DisplayMode::Blend { frames: 2 });
+    filter.apply(&mut [0xFF0000]);
+    let mut buffer = [0x0000FF];
+    filter.apply(&mut buffer);
+    assert_eq!(buffer, [0x7F007F]);
+}
+
+#[test]
+fn blend_averages_the_last_frames() {
+    let mut filter = DisplayFilter::new(DisplayMode::Blend { frames: 2 });
+    let mut first = [LIT, LIT, 0];
+    filter.apply(&mut first);
+    assert_eq!(first, [LIT, LIT, 0]); // only one frame so far
+
+    let mut second = [LIT, 0, 0];
+    filter.apply(&mut second);
+    assert_eq!(second, [LIT, 0x7F7F7F, 0]);
+
+    let mut third = [0, 0, LIT]; // the first frame has dropped out
+    filter.apply(&mut third);
+    assert_eq!(third, [0x7F7F7F, 0, 0x7F7F7F]);
+}
+
+#[test]
+fn a_resized_buffer_starts_the_filter_over() {
+    let mut filter = DisplayFilter::new(DisplayMode::Blend { frames: 3 });
+    filter.apply(&mut [LIT; 4]);
+    let mut bigger = [0; 8];
+    filter.apply(&mut bigger);
+    assert_eq!(bigger, [0; 8]);
+}
+
+#[test]
+fn roms_can_bring_their_own_mode() {
+    let dir = std::env::temp_dir().join(format!("chip8-phosphor-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.ch8").to_str().unwrap().to_string();
+    assert_eq!(DisplayMode::for_rom(&rom), Ok(None));
+
+    std::fs::write(format!("{}.display", rom), "blend:2\n").unwrap();
+    assert_eq!(DisplayMode::for_rom(&rom), Ok(Some(DisplayMode::Blend { frames: 2 })));
+
+    std::fs::write(format!("{}.display", rom), "sepia").unwrap();
+    assert!(DisplayMode::for_rom(&rom).unwrap_err().contains("game.ch8.display"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,116 @@
+use chip8_emulator::cpu::{Platform, CPU, WIDTH};
+use chip8_emulator::font::Font;
+use chip8_emulator::profile::MachineProfile;
+use chip8_emulator::timing::Timing;
+
+mod common;
+
+fn on(profile: MachineProfile, words: &[u16]) -> CPU {
+    common::load_with(words, |cpu| cpu.set_profile(profile).unwrap())
+}
+
+#[test]
+fn eti_660_loads_at_0x600_with_48_lines() {
+    // the 0 glyph at y 45, which loses its last 2 lines, then at y 50, which is line 2
+    let mut cpu = on(MachineProfile::eti_660(), &[0x602D, 0xA050, 0xD105, 0x6032, 0xD105]);
+    assert_eq!(cpu.pc(), 0x600);
+    assert_eq!(cpu.display().height(), 48);
+    assert_eq!(cpu.output_size(), (WIDTH, 48));
+    cpu.run(5).unwrap();
+    assert!(cpu.display().pixel(0, 45) && cpu.display().pixel(0, 47));
+    assert!(cpu.display().pixel(0, 2) && !cpu.display().pixel(0, 0));
+    assert_eq!(cpu.registers()[0xF], 0);
+
+    let mut buffer = vec![0; WIDTH * 48];
+    cpu.update_display_buffer(&mut buffer);
+    assert_eq!(buffer[47 * WIDTH], 0xFFFFFF);
+}
+
+#[test]
+fn roms_have_to_fit_in_ram() {
+    let room = 0x1000 - 0x600;
+    let mut cpu = CPU::new();
+    cpu.set_profile(MachineProfile::eti_660()).unwrap();
+    assert!(cpu.load_bytes(&vec![0; room + 1]).is_err());
+    assert!(cpu.load_bytes(&vec![0; room]).is_ok());
+}
+
+#[test]
+fn fonts_move_with_the_profile() {
+    let font = Font { small: vec![0xAA; 80], ..Font::default() };
+    let profile = MachineProfile { font_start: 0x100, font, ..MachineProfile::default() };
+    let mut cpu = on(profile, &[0x6003, 0xF029]);
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.index(), 0x10F);
+    assert_eq!(cpu.read_mem(0x10F), 0xAA);
+    assert_eq!(cpu.read_mem(0x50), 0); // the default font is gone
+}
+
+#[test]
+fn hires_is_a_vip_thing() {
+    // 1260, the patch, then a 0230 at 0x2C0
+    let mut words = vec![0x1260];
+    words.resize(0x60, 0);
+    words.push(0x0230);
+    let mut cpu = on(MachineProfile::dream_6800(), &words);
+    assert!(!cpu.hires());
+    assert_eq!(cpu.pc(), 0x200);
+    cpu = on(MachineProfile::vip(), &words);
+    assert!(cpu.hires());
+}
+
+#[test]
+fn platforms_can_still_move_the_start() {
+    let mut cpu = CPU::new();
+    cpu.set_profile(MachineProfile::eti_660()).unwrap();
+    cpu.set_platform(Platform::Chip8X);
+    assert_eq!(cpu.pc(), 0x300);
+    cpu.set_platform(Platform::Chip8);
+    assert_eq!(cpu.pc(), 0x600);
+}
+
+#[test]
+fn presets_and_checks() {
+    for name in MachineProfile::NAMES {
+        assert_eq!(MachineProfile::parse(name).unwrap().name, name);
+    }
+    assert!(MachineProfile::parse("c64").is_err());
+    assert!(matches!(MachineProfile::vip().timing, Timing::Vip(_)));
+    assert_eq!(MachineProfile::default().timing, Timing::default());
+
+    let mut cpu = CPU::new();
+    for bad in [
+        MachineProfile { height: 65, ..MachineProfile::default() },
+        MachineProfile { start: 0x201, ..MachineProfile::default() },
+        MachineProfile { font_start: 0xFC0, ..MachineProfile::default() },
+    ] {
+        assert!(cpu.set_profile(bad).is_err());
+    }
+}
+
+#[test]
+fn ram_stops_at_4k() {
+    let mut cpu = CPU::new();
+    let err = cpu.set_profile(MachineProfile { memory: 8192, ..MachineProfile::default() }).unwrap_err();
+    assert!(err.contains("8192 bytes"), "{}", err);
+    assert_eq!(cpu.profile(), &MachineProfile::default());
+
+    // so a save state always fits the layout load_state expects
+    let mut cpu = on(MachineProfile { memory: 4096, ..MachineProfile::vip() }, &[0x6001]);
+    let state = cpu.save_state().unwrap();
+    assert_eq!(state.len(), CPU::state_size());
+    cpu.load_state(&state).unwrap();
+}
+
+#[test]
+fn running_off_the_end_of_ram_fails() {
+    // the last instruction in memory is 7001, after which there is nowhere to go
+    for jit in [false, true] {
+        let mut cpu = on(MachineProfile::default(), &[0x1FFE]);
+        cpu.set_jit(jit);
+        cpu.write_mem(0xFFE, 0x70);
+        cpu.write_mem(0xFFF, 0x01);
+        assert_eq!(cpu.run(3), Err("PC 0x1000 is past the end of memory".to_string()));
+        assert_eq!((cpu.pc(), cpu.registers()[0]), (0x1000, 1));
+    }
+}
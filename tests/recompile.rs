@@ -0,0 +1,92 @@
+use chip8_emulator::cpu::CPU;
+use chip8_emulator::recompile::{analyze, emit};
+
+mod common;
+
+#[path = "recompiled/sample.rs"]
+mod sample;
+
+// words placed at the given addresses, zero in between
+fn rom(parts: &[(usize, &[u16])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for &(addr, words) in parts {
+        out.resize(addr - 0x200, 0);
+        out.extend(common::rom(words));
+    }
+    out
+}
+
+// a loop, subroutines, a BCD store, a draw, a BNNN jump and a routine that
+// rewrites code through an I set outside its own block
+fn sample_rom() -> Vec<u8> {
+    rom(&[
+        (0x200, &[0x6005, 0x6100, 0x2230, 0x7001, 0x3008, 0x1204, 0xA24A, 0x2240]),
+        (0x210, &[0xA260, 0xF033, 0x6000, 0xF029, 0xD015, 0xB21E, 0x121C, 0x6A07, 0x1220]),
+        (0x230, &[0x8104, 0x8125, 0x00EE]),
+        (0x240, &[0x6071, 0x6102, 0xF155, 0x6D01, 0x6E09, 0x6C03, 0x00EE]),
+    ])
+}
+
+#[test]
+fn sample_source_is_up_to_date() {
+    let rom = sample_rom();
+    let source = emit(&rom, &analyze(&rom), "sample.ch8");
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/recompiled/sample.rs");
+    if std::env::var("CHIP8_BLESS").is_ok() {
+        std::fs::write(path, &source).unwrap();
+    }
+    assert_eq!(source, std::fs::read_to_string(path).unwrap(), "run with CHIP8_BLESS=1 to regenerate");
+}
+
+#[test]
+fn recompiled_sample_matches_the_interpreter() {
+    let rom = sample_rom();
+    let mut interp = CPU::new();
+    let mut recompiled = CPU::new();
+    interp.load_bytes(&rom).unwrap();
+    recompiled.load_bytes(&rom).unwrap();
+    for count in [1, 2, 3, 1, 5, 8, 13, 21, 34] {
+        interp.run_frame(count).unwrap();
+        sample::run_frame(&mut recompiled, count).unwrap();
+        assert_eq!(interp.cycle(), recompiled.cycle());
+        assert_eq!(interp.save_state().unwrap(), recompiled.save_state().unwrap(), "after {} cycles", interp.cycle());
+    }
+    assert_eq!(recompiled.pc(), 0x220); // spinning after the BNNN jump
+    assert_eq!(recompiled.registers()[0xA], 7);
+    assert_eq!(recompiled.registers()[0x1], 0x02 + 0x02); // the rewritten 7102 ran instead of 6C03
+    assert_eq!(recompiled.registers()[0xC], 0);
+}
+
+#[test]
+fn analysis_follows_calls_and_skips() {
+    let rom = rom(&[(0x200, &[0x2206, 0x3001, 0x1200, 0x7001, 0x00EE])]);
+    let starts: Vec<usize> = analyze(&rom).blocks.iter().map(|b| b.start).collect();
+    // entry, return site, both ways out of the skip, subroutine
+    assert_eq!(starts, vec![0x200, 0x202, 0x204, 0x206]);
+}
+
+#[test]
+fn visible_self_modification_and_computed_jumps_are_left_to_the_interpreter() {
+    let rom = rom(&[
+        (0x200, &[0xA208, 0xF055, 0xB20C]), // writes over 208, then jumps through V0
+        (0x208, &[0x6001, 0x1208]),
+    ]);
+    let analysis = analyze(&rom);
+    assert_eq!(analysis.computed_jumps, vec![0x204]);
+    let block = |start| analysis.blocks.iter().find(|b| b.start == start);
+    assert!(!block(0x200).unwrap().self_modifying);
+    assert!(block(0x208).is_none()); // only reachable through BNNN
+    let source = emit(&rom, &analysis, "test");
+    assert!(source.contains("BNNN at 0x204"));
+}
+
+#[test]
+fn statically_written_blocks_are_not_compiled() {
+    let rom = rom(&[(0x200, &[0xA206, 0xF055, 0x1206, 0x6001, 0x1206])]);
+    let analysis = analyze(&rom);
+    let written = analysis.blocks.iter().find(|b| b.start == 0x206).unwrap();
+    assert!(written.self_modifying);
+    let source = emit(&rom, &analysis, "test");
+    assert!(source.contains("0x206-0x209 is written by the ROM"));
+    assert!(!source.contains("fn block_206"));
+}
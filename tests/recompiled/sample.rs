@@ -0,0 +1,137 @@
+// Recompiled from sample.ch8 by `chip8-emulator recompile`. Do not edit.
+//
+// 10 blocks compiled.
+// BNNN at 0x21A jumps to code that runs on the interpreter.
+
+use chip8_emulator::cpu::CPU;
+use chip8_emulator::decode::Op;
+
+const ROM: [u8; 78] = [
+    0x60, 0x05, 0x61, 0x00, 0x22, 0x30, 0x70, 0x01, 0x30, 0x08, 0x12, 0x04, 0xA2, 0x4A, 0x22, 0x40,
+    0xA2, 0x60, 0xF0, 0x33, 0x60, 0x00, 0xF0, 0x29, 0xD0, 0x15, 0xB2, 0x1E, 0x12, 0x1C, 0x6A, 0x07,
+    0x12, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x81, 0x04, 0x81, 0x25, 0x00, 0xEE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x60, 0x71, 0x61, 0x02, 0xF1, 0x55, 0x6D, 0x01, 0x6E, 0x09, 0x6C, 0x03, 0x00, 0xEE,
+];
+
+// whether the code at start..end is still what the ROM loaded there
+fn unchanged(cpu: &CPU, start: usize, end: usize) -> bool {
+    (start..end).all(|addr| cpu.read_mem(addr) == ROM[addr - 0x200])
+}
+
+// runs `count` instructions, like `CPU::run`
+pub fn run(cpu: &mut CPU, count: u32) -> Result<(), String> {
+    let mut left = count as usize;
+    while left > 0 {
+        left -= match cpu.pc() {
+            0x200 if left >= 2 && unchanged(cpu, 0x200, 0x204) => block_200(cpu).map(|_| 2)?,
+            0x204 if left >= 1 && unchanged(cpu, 0x204, 0x206) => block_204(cpu).map(|_| 1)?,
+            0x206 if left >= 2 && unchanged(cpu, 0x206, 0x20A) => block_206(cpu).map(|_| 2)?,
+            0x20A if left >= 1 && unchanged(cpu, 0x20A, 0x20C) => block_20a(cpu).map(|_| 1)?,
+            0x20C if left >= 2 && unchanged(cpu, 0x20C, 0x210) => block_20c(cpu).map(|_| 2)?,
+            0x210 if left >= 2 && unchanged(cpu, 0x210, 0x214) => block_210(cpu).map(|_| 2)?,
+            0x214 if left >= 4 && unchanged(cpu, 0x214, 0x21C) => block_214(cpu).map(|_| 4)?,
+            0x230 if left >= 3 && unchanged(cpu, 0x230, 0x236) => block_230(cpu).map(|_| 3)?,
+            0x240 if left >= 3 && unchanged(cpu, 0x240, 0x246) => block_240(cpu).map(|_| 3)?,
+            0x246 if left >= 4 && unchanged(cpu, 0x246, 0x24E) => block_246(cpu).map(|_| 4)?,
+            _ => cpu.execute().map(|_| 1)?,
+        };
+    }
+    Ok(())
+}
+
+// runs one 60Hz frame, like `CPU::run_frame`
+pub fn run_frame(cpu: &mut CPU, ipf: u32) -> Result<(), String> {
+    run(cpu, ipf)?;
+    cpu.tick_timers();
+    Ok(())
+}
+
+// 0x200-0x203
+fn block_200(cpu: &mut CPU) -> Result<(), String> {
+    let v = cpu.registers_mut();
+    v[0x0] = 0x05; // 200: 6005
+    v[0x1] = 0x00; // 202: 6100
+    cpu.advance(0x204, 2);
+    Ok(())
+}
+
+// 0x204-0x205
+fn block_204(cpu: &mut CPU) -> Result<(), String> {
+    cpu.advance(0x204, 0);
+    cpu.execute_at(0x204, Op::Call(0x230))?; // 204: 2230
+    Ok(())
+}
+
+// 0x206-0x209
+fn block_206(cpu: &mut CPU) -> Result<(), String> {
+    let v = cpu.registers_mut();
+    v[0x0] = v[0x0].wrapping_add(0x01); // 206: 7001
+    let next = if cpu.registers()[0x0] == 0x08 { 0x20C } else { 0x20A }; // 208: 3008
+    cpu.advance(next, 2);
+    Ok(())
+}
+
+// 0x20A-0x20B
+fn block_20a(cpu: &mut CPU) -> Result<(), String> {
+    cpu.advance(0x204, 1); // 20A: 1204
+    Ok(())
+}
+
+// 0x20C-0x20F
+fn block_20c(cpu: &mut CPU) -> Result<(), String> {
+    cpu.set_index(0x24A); // 20C: A24A
+    cpu.advance(0x20E, 1);
+    cpu.execute_at(0x20E, Op::Call(0x240))?; // 20E: 2240
+    Ok(())
+}
+
+// 0x210-0x213
+fn block_210(cpu: &mut CPU) -> Result<(), String> {
+    cpu.set_index(0x260); // 210: A260
+    cpu.advance(0x212, 1);
+    cpu.execute_at(0x212, Op::Bcd(0x0))?; // 212: F033
+    Ok(())
+}
+
+// 0x214-0x21B
+fn block_214(cpu: &mut CPU) -> Result<(), String> {
+    let v = cpu.registers_mut();
+    v[0x0] = 0x00; // 214: 6000
+    cpu.execute_at(0x216, Op::Font(0x0))?; // 216: F029
+    cpu.execute_at(0x218, Op::Draw(0x0, 0x1, 0x5))?; // 218: D015
+    cpu.advance(0x21A, 1);
+    cpu.execute_at(0x21A, Op::JumpOffset(0x2, 0x21E))?; // 21A: B21E
+    Ok(())
+}
+
+// 0x230-0x235
+fn block_230(cpu: &mut CPU) -> Result<(), String> {
+    let v = cpu.registers_mut();
+    let (sum, carry) = v[0x1].overflowing_add(v[0x0]); v[0x1] = sum; v[0xF] = carry as u8; // 230: 8104
+    let (diff, borrow) = v[0x1].overflowing_sub(v[0x2]); v[0x1] = diff; v[0xF] = !borrow as u8; // 232: 8125
+    cpu.advance(0x234, 2);
+    cpu.execute_at(0x234, Op::Return)?; // 234: 00EE
+    Ok(())
+}
+
+// 0x240-0x245
+fn block_240(cpu: &mut CPU) -> Result<(), String> {
+    let v = cpu.registers_mut();
+    v[0x0] = 0x71; // 240: 6071
+    v[0x1] = 0x02; // 242: 6102
+    cpu.advance(0x244, 2);
+    cpu.execute_at(0x244, Op::Store(0x1))?; // 244: F155
+    Ok(())
+}
+
+// 0x246-0x24D
+fn block_246(cpu: &mut CPU) -> Result<(), String> {
+    let v = cpu.registers_mut();
+    v[0xD] = 0x01; // 246: 6D01
+    v[0xE] = 0x09; // 248: 6E09
+    v[0xC] = 0x03; // 24A: 6C03
+    cpu.advance(0x24C, 3);
+    cpu.execute_at(0x24C, Op::Return)?; // 24C: 00EE
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use chip8_emulator::cpu::{StackConfig, CPU};
+
+mod common;
+
+fn load(words: &[u16], stack: StackConfig) -> CPU {
+    common::load_with(words, |cpu| cpu.set_stack_config(stack).unwrap())
+}
+
+#[test]
+fn runaway_recursion_overflows() {
+    for (stack, jit) in [(StackConfig::SCHIP, false), (StackConfig::VIP, false), (StackConfig::SCHIP, true)] {
+        let mut cpu = load(&[0x2200], stack);
+        cpu.set_jit(jit);
+        cpu.run(stack.depth as u32).unwrap();
+        assert_eq!(cpu.stack().len(), stack.depth);
+        let err = cpu.run(1).unwrap_err();
+        assert!(err.contains("Stack overflow"), "{}", err);
+        assert_eq!(cpu.stack().len(), stack.depth);
+        assert_eq!(cpu.cycle(), stack.depth as u64);
+    }
+}
+
+#[test]
+fn return_with_empty_stack_underflows() {
+    let mut cpu = load(&[0x6001, 0x00EE], StackConfig::default());
+    let err = cpu.run(2).unwrap_err();
+    assert!(err.contains("Stack underflow") && err.contains("0x202"), "{}", err);
+}
+
+#[test]
+fn memory_stack_lives_below_0xed0() {
+    let mut cpu = load(&[0x2204, 0x1202, 0x2208, 0x00EE, 0x00EE], StackConfig::VIP);
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.stack(), &[0x202, 0x206]);
+    assert_eq!([cpu.read_mem(0xECE), cpu.read_mem(0xECF)], [0x02, 0x02]);
+    assert_eq!([cpu.read_mem(0xECC), cpu.read_mem(0xECD)], [0x02, 0x06]);
+    cpu.run(2).unwrap();
+    assert_eq!(cpu.pc(), 0x202);
+    assert!(cpu.stack().is_empty());
+}
+
+#[test]
+fn memory_stack_returns_where_the_rom_says() {
+    // the subroutine at 208 overwrites its own return address with 0x20E
+    let rom = [
+        0x2208, 0x1200, 0x0000, 0x0000, // 200
+        0x6002, 0x610E, 0xAECE, 0xF155, // 208: return address = 020E
+        0x00EE, // 210
+    ];
+    let mut cpu = load(&rom, StackConfig::VIP);
+    cpu.run(6).unwrap();
+    assert_eq!(cpu.pc(), 0x20E);
+
+    // without the memory stack the write changes nothing
+    let mut cpu = load(&rom, StackConfig { in_memory: false, ..StackConfig::VIP });
+    cpu.run(6).unwrap();
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn depth_is_checked() {
+    let mut cpu = CPU::new();
+    assert!(cpu.set_stack_config(StackConfig { depth: 0, in_memory: false }).is_err());
+    assert!(cpu.set_stack_config(StackConfig { depth: 65, in_memory: false }).is_err());
+    assert!(cpu.set_stack_config(StackConfig { depth: 25, in_memory: true }).is_err());
+    assert!(cpu.set_stack_config(StackConfig { depth: 24, in_memory: true }).is_ok());
+    assert_eq!(StackConfig::parse("xochip").unwrap().depth, 64);
+    assert_eq!(StackConfig::parse("20").unwrap(), StackConfig { depth: 20, in_memory: false });
+    assert!(StackConfig::parse("deep").is_err());
+}
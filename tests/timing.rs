@@ -0,0 +1,67 @@
+use chip8_emulator::timing::{vip_cycles, Timing, VipTiming, DEFAULT_IPF};
+
+mod common;
+use common::load;
+
+#[test]
+fn budget_limits_instructions_per_frame() {
+    // 7001 costs 50 machine cycles and 1200 costs 52: 50 fit in the 2538 available
+    let mut cpu = load(&[0x7001, 0x1200]);
+    assert_eq!(VipTiming::cycles_available(), 2538);
+    let mut vip = VipTiming::new();
+    vip.run_frame(&mut cpu).unwrap();
+    assert_eq!(cpu.cycle(), 50);
+    assert_eq!(cpu.registers()[0], 25);
+}
+
+#[test]
+fn draw_waits_for_the_next_frame() {
+    let mut cpu = load(&[0xD005, 0x1200]);
+    let mut vip = VipTiming::new();
+    for _ in 0..10 {
+        vip.run_frame(&mut cpu).unwrap();
+    }
+    // one draw per frame: the first frame only draws, every later one jumps back and draws
+    assert_eq!(cpu.cycle(), 19);
+}
+
+#[test]
+fn long_instructions_run_into_the_next_frame() {
+    // 00E0 takes longer than a whole frame's budget
+    let mut cpu = load(&[0x00E0, 0x1200]);
+    assert!(vip_cycles(&cpu, 0x00E0) > VipTiming::cycles_available());
+    let mut vip = VipTiming::new();
+    vip.run_frame(&mut cpu).unwrap();
+    assert_eq!(cpu.cycle(), 1);
+    vip.run_frame(&mut cpu).unwrap();
+    assert!(cpu.cycle() > 2 && cpu.cycle() < 50);
+}
+
+#[test]
+fn costs_depend_on_operands() {
+    let cpu = load(&[0x6005]);
+    assert_eq!(vip_cycles(&cpu, 0x3000), 54); // V0 == 0, skip taken
+    assert_eq!(vip_cycles(&cpu, 0x3001), 50);
+    assert_eq!(vip_cycles(&cpu, 0xF255), 40 + 14 + 3 * 14);
+    assert!(vip_cycles(&cpu, 0xD005) < vip_cycles(&cpu, 0xD00F));
+}
+
+#[test]
+fn fixed_frames_run_exactly_ipf_instructions() {
+    let mut cpu = load(&[0x603C, 0xF018, 0x7101, 0x1204]);
+    let mut timing = Timing::Fixed { ipf: 7 };
+    for frame in 1..=5u64 {
+        timing.run_frame(&mut cpu).unwrap();
+        assert_eq!(cpu.cycle(), frame * 7);
+    }
+    // the sound timer was set in the first frame and ticked once per frame since
+    assert_eq!(cpu.sound_timer(), 60 - 5);
+    assert_eq!(cpu.registers()[1], 17); // 33 loop instructions after the setup, every other one an add
+}
+
+#[test]
+fn default_timing_is_ten_instructions_per_frame() {
+    assert_eq!(Timing::default(), Timing::Fixed { ipf: DEFAULT_IPF });
+    assert_eq!(Timing::parse("fixed").unwrap(), Timing::default());
+    assert!(Timing::parse("fast").is_err());
+}
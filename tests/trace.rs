@@ -0,0 +1,66 @@
+use chip8_emulator::cpu::CPU;
+use chip8_emulator::trace::{Level, TraceConfig, TraceEntry, Tracer};
+
+mod common;
+use common::rom;
+
+// V0 = 5, V1 = 7, draw the "5" glyph, then spin
+const PROGRAM: [u16; 5] = [0x6005, 0x6107, 0xF029, 0xD015, 0x1208];
+
+fn run(config: TraceConfig, capacity: usize, steps: usize) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom(&PROGRAM)).unwrap();
+    cpu.set_tracer(Tracer::ring(config, capacity));
+    for _ in 0..steps {
+        cpu.execute().unwrap();
+    }
+    cpu
+}
+
+fn lines(cpu: &CPU) -> Vec<String> {
+    cpu.tracer().entries().iter().map(|e| e.to_string()).collect()
+}
+
+#[test]
+fn off_by_default() {
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom(&PROGRAM)).unwrap();
+    cpu.execute().unwrap();
+    assert!(!cpu.tracer().enabled());
+    assert!(cpu.tracer().entries().is_empty());
+}
+
+#[test]
+fn instruction_records_state_before_execution() {
+    let cpu = run(TraceConfig::with_level(Level::Trace), 16, 4);
+    let lines = lines(&cpu);
+    assert_eq!(lines[0], "c=0 pc=200 op=6005 i=000 v=00000000000000000000000000000000");
+    assert_eq!(lines[2], "c=2 pc=204 op=F029 i=000 v=05070000000000000000000000000000");
+    assert_eq!(lines[4], "c=3 draw: sprite x=5 y=7 n=5 i=069 vf=0");
+    assert_eq!(cpu.cycle(), 4);
+}
+
+#[test]
+fn ring_keeps_most_recent_entries() {
+    let cpu = run(TraceConfig::with_level(Level::Trace), 3, 10);
+    let entries = cpu.tracer().entries();
+    assert_eq!(entries.len(), 3);
+    assert!(matches!(entries[2], TraceEntry::Instr { cycle: 9, pc: 0x208, .. }));
+}
+
+#[test]
+fn filters_by_category_address_and_opcode_class() {
+    let mut info = TraceConfig::with_level(Level::Info);
+    info.parse_categories("draw").unwrap();
+    assert_eq!(lines(&run(info, 16, 6)), ["c=3 draw: sprite x=5 y=7 n=5 i=069 vf=0"]);
+
+    let mut range = TraceConfig::with_level(Level::Trace);
+    range.parse_categories("cpu").unwrap();
+    range.parse_pc_range("202-204").unwrap();
+    let pcs: Vec<String> = lines(&run(range, 16, 6)).iter().map(|l| l[4..10].to_string()).collect();
+    assert_eq!(pcs, ["pc=202", "pc=204"]);
+
+    let mut ops = TraceConfig::with_level(Level::Trace);
+    ops.parse_opcode_classes("6").unwrap();
+    assert_eq!(lines(&run(ops, 16, 6)).len(), 2);
+}
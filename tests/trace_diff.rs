@@ -0,0 +1,76 @@
+use chip8_emulator::cpu::CPU;
+use chip8_emulator::trace::{Level, TraceConfig, Tracer};
+use chip8_emulator::trace_diff::{first_divergence, parse_trace, report};
+
+mod common;
+
+fn our_trace() -> String {
+    // V0 = 123, BCD it to 0x300, then spin
+    let prog = [0x607Bu16, 0xA300, 0xF033, 0x1206];
+    let rom = common::rom(&prog);
+    let mut cpu = CPU::new();
+    cpu.load_bytes(&rom).unwrap();
+    cpu.set_tracer(Tracer::ring(TraceConfig::with_level(Level::Trace), 100));
+    for _ in 0..6 {
+        cpu.execute().unwrap();
+    }
+    cpu.tracer().entries().iter().map(|e| format!("{}\n", e)).collect()
+}
+
+#[test]
+fn identical_traces_agree() {
+    let steps = parse_trace(&our_trace()).unwrap();
+    assert_eq!(steps.len(), 6);
+    assert_eq!(steps[2].writes, [(0x300, 1), (0x301, 2), (0x302, 3)]);
+    assert_eq!(first_divergence(&steps, &steps), None);
+}
+
+#[test]
+fn reports_first_register_and_write_difference() {
+    let ours = parse_trace(&our_trace()).unwrap();
+    let reference = our_trace()
+        .replace("cpu: write 300=01 301=02 302=03", "cpu: write 300=01 301=02 302=04")
+        .replace("c=5 pc=206 op=1206 i=300 v=7B", "c=5 pc=206 op=1206 i=300 v=7C");
+    let theirs = parse_trace(&reference).unwrap();
+
+    let div = first_divergence(&ours, &theirs).unwrap();
+    assert_eq!(div.cycle, 2);
+    assert_eq!(div.differences, ["write 302=03 only in ours", "write 302=04 only in reference"]);
+
+    let text = report(&ours, &theirs, &div, 1);
+    assert!(text.starts_with("first divergence at cycle 2:\n"));
+    assert!(text.contains("> c=2 pc=204 op=F033 i=300"));
+    assert!(text.contains("  c=1 pc=202 op=A300"));
+    assert!(text.contains("  c=3 pc=206 op=1206"));
+    assert!(!text.contains("c=4 "));
+}
+
+#[test]
+fn partial_reference_traces_align_by_cycle() {
+    let ours = parse_trace(&our_trace()).unwrap();
+    // a reference that only logs PC, and skips a few cycles
+    let theirs = parse_trace("# pc only\nc=0 pc=200\nc=3 pc=206\nc=5 pc=208\n").unwrap();
+    let div = first_divergence(&ours, &theirs).unwrap();
+    assert_eq!(div.cycle, 5);
+    assert_eq!(div.differences, ["PC: ours 206, reference 208"]);
+
+    assert!(parse_trace("c=3 pc=200\nc=2 pc=202\n").is_err());
+    assert!(parse_trace("pc=200\n").is_err());
+}
+
+#[test]
+fn a_trace_that_stops_early_diverges() {
+    let ours = parse_trace(&our_trace()).unwrap();
+    let short = parse_trace("c=0 pc=200\nc=3 pc=206\n").unwrap();
+    let div = first_divergence(&ours, &short).unwrap();
+    assert_eq!((div.cycle, div.ours, div.theirs), (4, 4, 2));
+    assert_eq!(div.differences, ["reference ends after cycle 3"]);
+    assert!(report(&ours, &short, &div, 1).contains("> c=4 pc=206"));
+
+    let div = first_divergence(&short, &ours).unwrap();
+    assert_eq!(div.cycle, 4);
+    assert_eq!(div.differences, ["ours ends after cycle 3"]);
+
+    let div = first_divergence(&ours, &[]).unwrap();
+    assert_eq!((div.cycle, div.differences[0].as_str()), (0, "reference is empty"));
+}
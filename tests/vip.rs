@@ -0,0 +1,110 @@
+use chip8_emulator::vip::{self, Vip};
+
+mod common;
+use common::rom;
+
+// A tiny CHIP-8 interpreter in 1802 code laid out like the VIP's: PC in R5,
+// I in RA, V0-VF at 0xEF0 through R6, a main loop run by R4 that fetches an
+// instruction and dispatches on its top nibble through a table to a routine
+// run by R3, which returns with SEP R4. It knows 1NNN, 6XNN, 7XNN and ANNN;
+// everything else does nothing.
+fn interpreter() -> Vec<u8> {
+    let mut code = vec![0; 0x50];
+    let parts: [(usize, &[u8]); 8] = [
+        // init: R5 = 0x200, R6 = 0x0E00, R4 = main, then into it
+        (0x00, &[0xF8, 0x02, 0xB5, 0xF8, 0x00, 0xA5, 0xF8, 0x0E, 0xB6, 0xF8, 0x10, 0xA4, 0xD4]),
+        // main: RF.0 = high byte, RE.0 = low byte, R6 = 0x0EF0 + X, R3 = table[high >> 4]
+        (
+            0x10,
+            &[
+                0x45, 0xAF, 0x45, 0xAE, 0x8F, 0xFA, 0x0F, 0xFC, 0xF0, 0xA6, // 10
+                0x8F, 0xF6, 0xF6, 0xF6, 0xF6, 0xFC, 0x40, 0xA7, 0x07, 0xA3, // 1A
+                0xD3, 0x30, 0x10, // 24: SEP R3, then back to main
+            ],
+        ),
+        (0x28, &[0xD4]),                                     // nothing
+        (0x29, &[0x8F, 0xFA, 0x0F, 0xB5, 0x8E, 0xA5, 0xD4]), // 1NNN
+        (0x30, &[0x8E, 0x56, 0xD4]),                         // 6XNN
+        (0x33, &[0xE6, 0x8E, 0xF4, 0x56, 0xD4]),             // 7XNN
+        (0x38, &[0x8F, 0xFA, 0x0F, 0xBA, 0x8E, 0xAA, 0xD4]), // ANNN
+        (0x40, &[0x28, 0x29, 0x28, 0x28, 0x28, 0x28, 0x30, 0x33, 0x28, 0x28, 0x38, 0x28, 0x28, 0x28, 0x28, 0x28]),
+    ];
+    for (at, bytes) in parts {
+        code[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+    code
+}
+
+#[test]
+fn interpreter_agrees_with_cpu() {
+    let rom = rom(&[0x6005, 0x7003, 0xA123, 0x6A10, 0x7AF8, 0x1200]);
+    let mut vip = Vip::new(&interpreter(), None, 4096, &rom).unwrap();
+    let mut cpu = vip::reference_cpu(&rom).unwrap();
+    assert_eq!(vip::check(&mut vip, &mut cpu, 30).unwrap(), None);
+    assert_eq!(vip.chip8_registers()[0], 8);
+    assert_eq!(vip.chip8_registers()[0xA], 0x08);
+    assert_eq!(vip.chip8_index(), 0x123);
+}
+
+#[test]
+fn divergence_is_reported() {
+    // an interpreter whose 7XNN loads instead of adding
+    let mut broken = interpreter();
+    broken[0x47] = 0x30;
+    let rom = rom(&[0x6005, 0x7003, 0x1200]);
+    let mut vip = Vip::new(&broken, None, 4096, &rom).unwrap();
+    let mut cpu = vip::reference_cpu(&rom).unwrap();
+    let report = vip::check(&mut vip, &mut cpu, 10).unwrap().unwrap();
+    assert!(report.contains("7003 at 0x202") && report.contains("V0 is 0x03"), "{}", report);
+}
+
+#[test]
+fn sizes_are_checked() {
+    assert!(Vip::new(&[0; 0x201], None, 4096, &[]).is_err());
+    assert!(Vip::new(&[], None, 1024, &[]).is_err());
+    assert!(Vip::new(&[], None, 2048, &[0; 0x601]).is_err());
+    assert!(Vip::new(&[], None, 3072, &[0; 0xA00]).is_ok());
+}
+
+// turns the 1861 on (or not) and spins, with an interrupt routine that
+// points R0 at 0xF00 for each frame's DMA
+fn video_program(display_on: bool) -> Vec<u8> {
+    let mut code = vec![0; 0x30];
+    let parts: [(usize, &[u8]); 3] = [
+        // R2 = 0x0EFF, R1 = 0x0020, R3 = 0x0010, SEP R3
+        (0x00, &[0xF8, 0x0E, 0xB2, 0xF8, 0xFF, 0xA2, 0xF8, 0x20, 0xA1, 0xF8, 0x10, 0xA3, 0xD3]),
+        // SEX 2, INP 1 (or NOP), spin
+        (0x10, &[0xE2, if display_on { 0x69 } else { 0xC4 }, 0x30, 0x12]),
+        // 1F: RET; 20: DEC R2, SAV, R0 = 0x0F00, back to the RET
+        (0x1F, &[0x70, 0x22, 0x78, 0xF8, 0x0F, 0xB0, 0xF8, 0x00, 0xA0, 0x30, 0x1F]),
+    ];
+    for (at, bytes) in parts {
+        code[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+    code
+}
+
+#[test]
+fn the_1861_shows_memory_from_r0() {
+    let page: Vec<u8> = (0..=255).collect();
+    let mut rom = vec![0; 0xD00];
+    rom.extend_from_slice(&page); // at 0xF00
+    let mut vip = Vip::new(&video_program(true), None, 4096, &rom).unwrap();
+    for _ in 0..3 {
+        vip.run_frame().unwrap();
+    }
+    assert_eq!(vip.frames(), 3);
+    // 128 lines of 8 bytes run past the end of RAM and wrap around to 0
+    for (line, row) in vip.video().iter().enumerate() {
+        let at = (0xF00 + line * 8) % 4096;
+        let bytes: [u8; 8] = vip.ram()[at..at + 8].try_into().unwrap();
+        assert_eq!(*row, u64::from_be_bytes(bytes), "line {}", line);
+    }
+    assert_eq!(vip.display().rows()[1], u64::from_be_bytes(vip.ram()[0xF20..0xF28].try_into().unwrap()));
+    assert!(vip.core().ie);
+
+    let mut vip = Vip::new(&video_program(false), None, 4096, &rom).unwrap();
+    vip.run_frame().unwrap();
+    assert!(vip.video().iter().all(|&row| row == 0));
+    assert_eq!(vip.core().r[0], 0x0D);
+}